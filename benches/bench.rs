@@ -32,5 +32,57 @@ benchmarks! {
     bench_day: day1
 }
 
-criterion_group!(benches, benchmark_function);
+/// A ball of cubes with scattered holes, some of which end up as air pockets
+fn large_droplet(radius: i32) -> aoc2022::day18::VoxelSet {
+    use aoc2022::day18::Position;
+
+    let mut cubes = Vec::new();
+    for x in -radius..=radius {
+        for y in -radius..=radius {
+            for z in -radius..=radius {
+                let noise = (x.wrapping_mul(73856093) ^ y.wrapping_mul(19349663) ^ z.wrapping_mul(83492791)).rem_euclid(7);
+                if x * x + y * y + z * z <= radius * radius && noise != 0 {
+                    cubes.push(Position::new(x, y, z));
+                }
+            }
+        }
+    }
+    cubes.into_iter().collect()
+}
+
+fn day18_backends(c: &mut Criterion) {
+    use aoc2022::day18::{DenseVoxels, Droplet};
+
+    let mut group = c.benchmark_group("day18 backends");
+    group.sample_size(10);
+    let voxels = large_droplet(40);
+    let dense = DenseVoxels::new(&voxels);
+    group.bench_function("hash build", |b| b.iter(|| large_droplet(40)));
+    group.bench_function("dense build", |b| b.iter(|| DenseVoxels::new(&voxels)));
+    group.bench_function("hash surface", |b| b.iter(|| Droplet::surface_area(&voxels)));
+    group.bench_function("dense surface", |b| b.iter(|| Droplet::surface_area(&dense)));
+    group.bench_function("hash exterior", |b| b.iter(|| Droplet::exterior_area(&voxels)));
+    group.bench_function("dense exterior", |b| b.iter(|| Droplet::exterior_area(&dense)));
+    group.finish();
+}
+
+fn day24_engines(c: &mut Criterion) {
+    use aoc2022::day24::{generator, Engine, Solver};
+
+    let valley = generator(include_str!("../input/day24.txt"));
+    let (start, end) = (valley.start().clone(), valley.end().clone());
+    let waypoints = [start.clone(), end.clone(), start.clone(), end.clone()];
+    let mut group = c.benchmark_group("day24 engines");
+    group.sample_size(10);
+    group.bench_function("queue", |b| b.iter(|| {
+        let t = valley.find_time(&start, &end.add(0, -1), 0);
+        let t = valley.find_time(&end, &start.add(0, 1), t);
+        valley.find_time(&start, &end.add(0, -1), t)
+    }));
+    group.bench_function("states", |b| b.iter(|| Solver::with_engine(&valley, Engine::States).trip(&waypoints, 0)));
+    group.bench_function("frontier", |b| b.iter(|| Solver::with_engine(&valley, Engine::Frontier).trip(&waypoints, 0)));
+    group.finish();
+}
+
+criterion_group!(benches, benchmark_function, day18_backends, day24_engines);
 criterion_main!(benches);
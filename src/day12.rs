@@ -1,5 +1,6 @@
 use std::borrow::BorrowMut;
 use std::collections::VecDeque;
+use colored::Colorize;
 
 pub fn generator(input: &str) -> ElevationMap {
   ElevationMap::new(input)
@@ -51,14 +52,22 @@ impl ElevationMap {
     }
   }
 
-  fn compute_steps(&self, best: bool) -> u32 {
+  /// The shortest route up to the end, starting at the start or, when `best` is set, at whichever
+  /// cell of the same elevation is closest
+  fn compute_steps(&self, best: bool) -> Option<Route> {
     let neighbors: [(isize, isize); 4] = [(-1, 0), (1, 0), (0, -1), (0, 1)];
     let mut min_steps: Vec<Vec<u32>> = vec![vec![u32::MAX; self.map.first().unwrap().len()]; self.map.len()];
+    // The neighbour each cell was reached from, one step closer to the end
+    let mut next: Vec<Vec<Option<Position>>> = vec![vec![None; self.width]; self.height];
     let mut to_check: VecDeque<Position> = VecDeque::new();
     *min_steps[self.end.y][self.end.x].borrow_mut() = 0;
     to_check.push_back(self.end.clone());
     let mut best_start_steps = u32::MAX;
+    let mut best_start = None;
     let start_code = self.map[self.start.y][self.start.x];
+    if self.start == self.end {
+      return Some(Route { path: vec![self.end.clone()] });
+    }
     while !to_check.is_empty() {
       let curr = to_check.pop_front().unwrap();
       let min_elevation = self.map[curr.y][curr.x] as u32 - 1;
@@ -81,34 +90,108 @@ impl ElevationMap {
           continue;
         }
         *min_steps[n_y as usize][n_x as usize].borrow_mut() = next_steps;
-        to_check.push_back(next_pos);
-        if (n_y as usize == self.start.y && n_x as usize == self.start.x)
-          || (best && self.map[n_y as usize][n_x as usize] == start_code) {
-          best_start_steps = best_start_steps.min(next_steps);
+        next[next_pos.y][next_pos.x] = Some(curr.clone());
+        if ((n_y as usize == self.start.y && n_x as usize == self.start.x)
+          || (best && self.map[n_y as usize][n_x as usize] == start_code))
+          && next_steps < best_start_steps {
+          best_start_steps = next_steps;
+          best_start = Some(next_pos.clone());
         }
+        to_check.push_back(next_pos);
       }
     }
 
-    best_start_steps
+    let mut path = vec![best_start?];
+    while let Some(n) = &next[path.last().unwrap().y][path.last().unwrap().x] {
+      path.push(n.clone());
+    }
+    Some(Route { path })
+  }
+
+  /// The shortest route from the start to the end
+  pub fn route(&self) -> Option<Route> {
+    self.compute_steps(false)
+  }
+
+  /// The shortest route to the end from any of the lowest cells
+  pub fn best_route(&self) -> Option<Route> {
+    self.compute_steps(true)
+  }
+
+  /// The map with the route drawn on it as in the puzzle, with `.` off the route
+  pub fn draw_route(&self, route: &Route) -> String {
+    let mut grid = vec![vec!['.'; self.width]; self.height];
+    Self::mark_route(&mut grid, route, 'E');
+    grid.iter().map(|row| row.iter().collect::<String>()).collect::<Vec<String>>().join("\n")
+  }
+
+  fn mark_route(grid: &mut [Vec<char>], route: &Route, end: char) {
+    for (from, to) in route.path.iter().zip(route.path.iter().skip(1)) {
+      grid[from.y][from.x] = match (to.x as isize - from.x as isize, to.y as isize - from.y as isize) {
+        (1, 0) => '>',
+        (-1, 0) => '<',
+        (0, 1) => 'v',
+        _ => '^',
+      };
+    }
+    if let Some(last) = route.path.last() {
+      grid[last.y][last.x] = end;
+    }
+  }
+
+  /// The heightmap shaded from green valleys to white peaks, with the route if any drawn over it
+  pub fn elevation_view(&self, route: Option<&Route>) -> String {
+    let mut grid: Vec<Vec<char>> = self.map.iter()
+      .map(|row| row.iter().map(|c| char::from_u32(*c).unwrap()).collect())
+      .collect();
+    grid[self.start.y][self.start.x] = 'S';
+    grid[self.end.y][self.end.x] = 'E';
+    if let Some(route) = route {
+      Self::mark_route(&mut grid, route, 'E');
+    }
+    grid.iter().zip(&self.map).map(|(row, elevations)| {
+      row.iter().zip(elevations).map(|(c, e)| {
+        let t = (e.saturating_sub('a' as u32)).min(25) as f64 / 25.0;
+        let shade = |low: f64, high: f64| (low + (high - low) * t) as u8;
+        c.to_string().black().on_truecolor(shade(30.0, 240.0), shade(120.0, 240.0), shade(30.0, 240.0)).to_string()
+      }).collect::<String>()
+    }).collect::<Vec<String>>().join("\n")
   }
 }
 
 pub fn part1(elev_map: &ElevationMap) -> u32 {
-  elev_map.compute_steps(false)
+  elev_map.route().map_or(u32::MAX, |r| r.steps())
 }
 
 pub fn part2(elev_map: &ElevationMap) -> u32 {
-  elev_map.compute_steps(true)
+  elev_map.best_route().map_or(u32::MAX, |r| r.steps())
+}
+
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct Position {
+  pub x: usize,
+  pub y: usize,
 }
 
+/// The cells visited on the way up, from the starting cell to the end
 #[derive(Clone, Eq, PartialEq, Debug)]
-struct Position {
-  x: usize,
-  y: usize,
+pub struct Route {
+  pub path: Vec<Position>,
+}
+
+impl Route {
+  pub fn start(&self) -> &Position {
+    self.path.first().unwrap()
+  }
+
+  pub fn steps(&self) -> u32 {
+    self.path.len() as u32 - 1
+  }
 }
 
 #[cfg(test)]
 mod tests {
+  use regex::Regex;
   use crate::day12::{generator, part1, part2, Position};
 
   fn input() -> String {
@@ -141,4 +224,48 @@ mod tests {
     let elev_map = generator(input().as_str());
     assert_eq!(29, part2(&elev_map));
   }
-}
\ No newline at end of file
+
+  #[test]
+  fn test_route() {
+    let elev_map = generator(input().as_str());
+    let route = elev_map.route().unwrap();
+    assert_eq!(31, route.steps());
+    assert_eq!(&Position { x: 0, y: 0 }, route.start());
+    assert_eq!(&Position { x: 5, y: 2 }, route.path.last().unwrap());
+    assert!(route.path.windows(2).all(|w| {
+      w[0].x.abs_diff(w[1].x) + w[0].y.abs_diff(w[1].y) == 1
+        && elev_map.map[w[1].y][w[1].x] <= elev_map.map[w[0].y][w[0].x] + 1
+    }));
+
+    let best = elev_map.best_route().unwrap();
+    assert_eq!(29, best.steps());
+    assert_eq!(&Position { x: 0, y: 4 }, best.start());
+
+    let blocked = generator("Sz\nzE");
+    assert_eq!(None, blocked.route());
+    assert_eq!(u32::MAX, part1(&blocked));
+  }
+
+  #[test]
+  fn test_draw_route() {
+    let elev_map = generator(input().as_str());
+    let route = elev_map.route().unwrap();
+    assert_eq!([
+      "v..v<<<<",
+      ">v.vv<<^",
+      ".v.v>E^^",
+      ".>v>>>^^",
+      "..>>>>>^",
+    ].join("\n"), elev_map.draw_route(&route));
+  }
+
+  #[test]
+  fn test_elevation_view() {
+    let elev_map = generator(input().as_str());
+    let strip = |s: String| Regex::new("\x1b\\[[0-9;]*m").unwrap().replace_all(&s, "").to_string();
+    assert_eq!(input(), strip(elev_map.elevation_view(None)));
+    let route = elev_map.route().unwrap();
+    let view = strip(elev_map.elevation_view(Some(&route)));
+    assert_eq!("vabv<<<<", view.lines().next().unwrap());
+  }
+}
@@ -1,8 +1,8 @@
-use std::borrow::BorrowMut;
 use std::cmp::Ordering;
 use std::fmt::{Display, Formatter};
 use std::iter::Peekable;
-use std::str::Chars;
+use std::str::{CharIndices, FromStr};
+use serde::{Deserialize, Serialize};
 use crate::day13::PacketItem::{INT, LIST};
 
 pub fn generator(input: &str) -> Vec<Packet> {
@@ -26,34 +26,55 @@ pub fn part1(packets: &Vec<Packet>) -> usize {
 }
 
 pub fn part2(packets: &Vec<Packet>) -> usize {
-  let mut packets = packets.clone();
-  let start_mark = make_packet("[[2]]");
-  let end_mark = make_packet("[[6]]");
-  packets.push(start_mark.clone());
-  packets.push(end_mark.clone());
-  packets.sort();
-
-  let s_idx = match packets.binary_search(&start_mark) {
-    Ok(i) => i + 1,
-    _ => panic!("Did not find starting marker")
-  };
-  let e_idx = match packets.binary_search(&end_mark) {
-    Ok(i) => i + 1,
-    _ => panic!("Did not find starting marker")
-  };
-  s_idx * e_idx
-}
-
-type Packet = PacketItem;
+  decoder_key(packets, &[make_packet("[[2]]"), make_packet("[[6]]")])
+}
+
+/// The product of the 1-based positions the divider packets take when they are sorted along
+/// with the packets. Dividers equal to a packet are placed before it.
+pub fn decoder_key(packets: &[Packet], dividers: &[Packet]) -> usize {
+  dividers.iter().map(|divider| {
+    let before = packets.iter().chain(dividers).filter(|&p| p < divider).count();
+    before + 1
+  }).product()
+}
+
+pub type Packet = PacketItem;
 
 fn make_packet(input: &str) -> Packet {
-  match PacketItem::from(input) {
-    l @ LIST(_) => l,
-    r => panic!("Expecting a list, but received {:?}", r)
+  parse_packet(input).unwrap_or_else(|e| panic!("Invalid packet {input}: {e}"))
+}
+
+/// Parse a packet, which unlike an item has to be a list
+pub fn parse_packet(input: &str) -> Result<Packet, PacketError> {
+  match input.parse()? {
+    l @ LIST(_) => Ok(l),
+    INT(_) => Err(PacketError::NotAList),
   }
 }
 
-#[derive(Debug, Eq, PartialEq, Clone)]
+/// Why a packet could not be parsed, with the character position where it went wrong
+#[derive(Debug, Eq, PartialEq)]
+pub enum PacketError {
+  Unexpected { position: usize, found: char },
+  UnexpectedEnd { position: usize },
+  NumberTooLarge { position: usize },
+  NotAList,
+}
+
+impl Display for PacketError {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    match self {
+      PacketError::Unexpected { position, found } =>
+        write!(f, "unexpected '{found}' at position {position}"),
+      PacketError::UnexpectedEnd { position } => write!(f, "unexpected end at position {position}"),
+      PacketError::NumberTooLarge { position } => write!(f, "number too large at position {position}"),
+      PacketError::NotAList => write!(f, "a packet has to be a list"),
+    }
+  }
+}
+
+#[derive(Debug, Eq, PartialEq, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
 pub enum PacketItem {
   INT(u32),
   LIST(Vec<PacketItem>),
@@ -67,6 +88,21 @@ impl Display for PacketItem {
   }
 }
 
+/// Parse an item, allowing whitespace between values as JSON does
+impl FromStr for PacketItem {
+  type Err = PacketError;
+
+  fn from_str(input: &str) -> Result<Self, Self::Err> {
+    let mut parser = Parser { chars: input.char_indices().peekable(), len: input.len() };
+    let result = parser.make_item()?;
+    parser.skip_whitespace();
+    match parser.chars.next() {
+      Some((position, found)) => Err(PacketError::Unexpected { position, found }),
+      None => Ok(result),
+    }
+  }
+}
+
 impl PartialOrd<Self> for PacketItem {
   fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
     Some(self.cmp(other))
@@ -109,14 +145,13 @@ impl Ord for PacketItem {
 }
 
 impl PacketItem {
-  fn from(input: &str) -> PacketItem {
-    let mut chars = input.chars();
-    let mut itr = chars.borrow_mut().peekable();
-    let result = PacketItem::make_list(&mut itr);
-    match itr.next() {
-      Some(c) => panic!("Unexpected character {c} after end of packet"),
-      None => result
-    }
+  /// Convert to a JSON array, which is the same text as `Display` gives
+  pub fn to_json(&self) -> String {
+    serde_json::to_string(self).unwrap()
+  }
+
+  pub fn from_json(input: &str) -> Result<PacketItem, serde_json::Error> {
+    serde_json::from_str(input)
   }
 
   fn make_output(&self, output: &mut String) {
@@ -137,53 +172,203 @@ impl PacketItem {
       }
     }
   }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Side {
+  Left,
+  Right,
+}
 
-  fn make_list(input: &mut Peekable<&mut Chars>) -> PacketItem {
-    match input.next() {
-      Some('[') => {}
-      x => panic!("Unexpected start: {:?} to a list", x),
+impl Display for Side {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    match self {
+      Side::Left => write!(f, "left"),
+      Side::Right => write!(f, "right"),
     }
+  }
+}
 
-    let mut buffer: Vec<PacketItem> = Vec::new();
-    loop {
-      match input.peek() {
-        Some(',') => {
-          input.next();
+/// A line of a comparison trace, indented by its depth
+#[derive(Debug, Eq, PartialEq)]
+pub enum Step {
+  Compare { depth: usize, left: String, right: String },
+  /// The integer on the given side was wrapped in a list to compare it with a list
+  Convert { depth: usize, side: Side, converted: String },
+  /// The integer on the given side is smaller, which decides the order
+  Smaller { depth: usize, side: Side },
+  /// The list on the given side has no more items, which decides the order
+  RanOut { depth: usize, side: Side },
+}
+
+impl Display for Step {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    let (depth, text) = match self {
+      Step::Compare { depth, left, right } => (depth, format!("Compare {left} vs {right}")),
+      Step::Convert { depth, side, converted } =>
+        (depth, format!("Mixed types; convert {side} to {converted} and retry comparison")),
+      Step::Smaller { depth, side: Side::Left } =>
+        (depth, "Left side is smaller, so input is in the right order".to_string()),
+      Step::Smaller { depth, side: Side::Right } =>
+        (depth, "Right side is smaller, so input is not in the right order".to_string()),
+      Step::RanOut { depth, side: Side::Left } =>
+        (depth, "Left side ran out of items, so inputs are in the right order".to_string()),
+      Step::RanOut { depth, side: Side::Right } =>
+        (depth, "Right side ran out of items, so inputs are not in the right order".to_string()),
+    };
+    write!(f, "{}- {}", "  ".repeat(*depth), text)
+  }
+}
+
+/// How two packets were compared, step by step as in the puzzle text
+#[derive(Debug, Eq, PartialEq)]
+pub struct Comparison {
+  pub ordering: Ordering,
+  pub steps: Vec<Step>,
+  /// The list indices leading to where the order was decided, or `None` if both are equal
+  pub decided_at: Option<Vec<usize>>,
+}
+
+impl Display for Comparison {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    for step in &self.steps {
+      writeln!(f, "{step}")?;
+    }
+    Ok(())
+  }
+}
+
+/// Explain the comparison of each pair of packets, under a heading for each pair
+pub fn explain_pairs(packets: &[Packet]) -> String {
+  packets.chunks(2).enumerate().map(|(idx, chunk)| {
+    let comparison = chunk.first().unwrap().explain_cmp(chunk.last().unwrap());
+    format!("== Pair {} ==\n{}", idx + 1, comparison)
+  }).collect::<Vec<String>>().join("\n")
+}
+
+impl PacketItem {
+  /// Compare the same way as `cmp`, recording each step taken to decide the order
+  pub fn explain_cmp(&self, other: &PacketItem) -> Comparison {
+    let mut steps = Vec::new();
+    let mut path = Vec::new();
+    let mut decided_at = None;
+    let ordering = self.explain(other, 0, &mut path, &mut steps, &mut decided_at);
+    Comparison { ordering, steps, decided_at }
+  }
+
+  fn explain(&self, other: &PacketItem, depth: usize, path: &mut Vec<usize>, steps: &mut Vec<Step>,
+             decided_at: &mut Option<Vec<usize>>) -> Ordering {
+    steps.push(Step::Compare { depth, left: self.to_string(), right: other.to_string() });
+    let depth = depth + 1;
+    let (side, ordering) = match (self, other) {
+      (INT(s), INT(o)) => {
+        match s.cmp(o) {
+          Ordering::Less => steps.push(Step::Smaller { depth, side: Side::Left }),
+          Ordering::Greater => steps.push(Step::Smaller { depth, side: Side::Right }),
+          Ordering::Equal => return Ordering::Equal,
         }
-        Some(']') => {
-          input.next();
-          break;
+        (None, s.cmp(o))
+      }
+      (LIST(s), LIST(o)) => {
+        for i in 0..s.len().min(o.len()) {
+          path.push(i);
+          let ordering = s[i].explain(&o[i], depth, path, steps, decided_at);
+          path.pop();
+          if ordering.is_ne() {
+            return ordering;
+          }
         }
-        Some('[') => {
-          buffer.push(PacketItem::make_list(input));
+        match s.len().cmp(&o.len()) {
+          Ordering::Less => steps.push(Step::RanOut { depth, side: Side::Left }),
+          Ordering::Greater => steps.push(Step::RanOut { depth, side: Side::Right }),
+          Ordering::Equal => return Ordering::Equal,
         }
-        Some(c) if c.is_digit(10) => buffer.push(PacketItem::make_int(input)),
-        x => panic!("Unexpected {:?}", x),
+        (None, s.len().cmp(&o.len()))
+      }
+      (INT(_), LIST(_)) => {
+        let converted = LIST(vec![self.clone()]);
+        steps.push(Step::Convert { depth, side: Side::Left, converted: converted.to_string() });
+        (Some(Side::Left), converted.explain(other, depth, path, steps, decided_at))
+      }
+      (LIST(_), INT(_)) => {
+        let converted = LIST(vec![other.clone()]);
+        steps.push(Step::Convert { depth, side: Side::Right, converted: converted.to_string() });
+        (Some(Side::Right), self.explain(&converted, depth, path, steps, decided_at))
       }
+    };
+    if side.is_none() && decided_at.is_none() {
+      let mut at = path.clone();
+      if let (LIST(s), LIST(o)) = (self, other) {
+        at.push(s.len().min(o.len()));
+      }
+      *decided_at = Some(at);
     }
-    LIST(buffer)
+    ordering
   }
+}
 
-  fn make_int(input: &mut Peekable<&mut Chars>) -> PacketItem {
-    let mut n: u32 = 0;
+struct Parser<'a> {
+  chars: Peekable<CharIndices<'a>>,
+  len: usize,
+}
+
+impl Parser<'_> {
+  fn skip_whitespace(&mut self) {
+    while self.chars.next_if(|(_, c)| c.is_whitespace()).is_some() {}
+  }
+
+  fn peek(&mut self) -> Result<(usize, char), PacketError> {
+    self.skip_whitespace();
+    self.chars.peek().copied().ok_or(PacketError::UnexpectedEnd { position: self.len })
+  }
+
+  fn make_item(&mut self) -> Result<PacketItem, PacketError> {
+    match self.peek()? {
+      (_, '[') => self.make_list(),
+      (_, c) if c.is_ascii_digit() => self.make_int(),
+      (position, found) => Err(PacketError::Unexpected { position, found }),
+    }
+  }
+
+  fn make_list(&mut self) -> Result<PacketItem, PacketError> {
+    self.chars.next();
+    let mut buffer: Vec<PacketItem> = Vec::new();
+    if let (_, ']') = self.peek()? {
+      self.chars.next();
+      return Ok(LIST(buffer));
+    }
     loop {
-      match input.peek() {
-        Some(c) if c.is_digit(10) => {
-          n *= 10;
-          n += c.to_digit(10).unwrap();
-          input.next();
+      buffer.push(self.make_item()?);
+      match self.peek()? {
+        (_, ',') => {
+          self.chars.next();
+        }
+        (_, ']') => {
+          self.chars.next();
+          break;
         }
-        _ => break,
+        (position, found) => return Err(PacketError::Unexpected { position, found }),
       }
     }
-    INT(n)
+    Ok(LIST(buffer))
+  }
+
+  fn make_int(&mut self) -> Result<PacketItem, PacketError> {
+    let mut n: u32 = 0;
+    while let Some((position, c)) = self.chars.next_if(|(_, c)| c.is_ascii_digit()) {
+      n = n.checked_mul(10)
+           .and_then(|n| n.checked_add(c.to_digit(10).unwrap()))
+           .ok_or(PacketError::NumberTooLarge { position })?;
+    }
+    Ok(INT(n))
   }
 }
 
 #[cfg(test)]
 mod tests {
   use std::cmp::Ordering;
-  use crate::day13::{PacketItem, make_packet, generator, part1, part2};
+  use crate::day13::{decoder_key, explain_pairs, PacketError, PacketItem, make_packet, generator, parse_packet,
+                     part1, part2};
   use crate::day13::PacketItem::LIST;
 
   fn input() -> String {
@@ -241,4 +426,105 @@ mod tests {
     let packets = generator(input().as_str());
     assert_eq!(140, part2(&packets));
   }
+
+  #[test]
+  fn test_parse_errors() {
+    assert_eq!(Err(PacketError::NotAList), parse_packet("12"));
+    assert_eq!(Err(PacketError::UnexpectedEnd { position: 4 }), parse_packet("[1,2"));
+    assert_eq!(Err(PacketError::UnexpectedEnd { position: 3 }), parse_packet("[1,"));
+    assert_eq!(Err(PacketError::UnexpectedEnd { position: 0 }), parse_packet(""));
+    assert_eq!(Err(PacketError::Unexpected { position: 3, found: ']' }), parse_packet("[1,]"));
+    assert_eq!(Err(PacketError::Unexpected { position: 3, found: '2' }), parse_packet("[1 2]"));
+    assert_eq!(Err(PacketError::Unexpected { position: 1, found: ',' }), parse_packet("[,1]"));
+    assert_eq!(Err(PacketError::Unexpected { position: 3, found: 'a' }), parse_packet("[[]a]"));
+    assert_eq!(Err(PacketError::Unexpected { position: 5, found: ']' }), parse_packet("[[1]]]"));
+    assert_eq!(Err(PacketError::NumberTooLarge { position: 10 }), parse_packet("[4294967296]"));
+    assert_eq!("unexpected '2' at position 3", parse_packet("[1 2]").unwrap_err().to_string());
+    assert_eq!(Ok(PacketItem::INT(7)), "7".parse());
+  }
+
+  #[test]
+  fn test_round_trip() {
+    for line in input().lines().filter(|l| !l.is_empty()) {
+      let packet = make_packet(line);
+      assert_eq!(line, packet.to_string());
+      assert_eq!(line, packet.to_json());
+      assert_eq!(packet, PacketItem::from_json(line).unwrap());
+      assert_eq!(Ok(packet.clone()), parse_packet(&packet.to_string()));
+    }
+    // Whitespace is accepted but not kept
+    let packet = parse_packet(" [ 1 , [ ] ,[2, 3] ] ").unwrap();
+    assert_eq!("[1,[],[2,3]]", packet.to_string());
+    assert_eq!(packet, PacketItem::from_json("[1, [], [2, 3]]").unwrap());
+    assert!(PacketItem::from_json("[1, -2]").is_err());
+    assert!(PacketItem::from_json("[1, \"2\"]").is_err());
+  }
+
+  #[test]
+  fn test_decoder_key() {
+    let packets = generator(input().as_str());
+    assert_eq!(10 * 14, decoder_key(&packets, &[make_packet("[[2]]"), make_packet("[[6]]")]));
+    assert_eq!(10, decoder_key(&packets, &[make_packet("[[2]]")]));
+    assert_eq!(1, decoder_key(&packets, &[make_packet("[]")]));
+    assert_eq!(1, decoder_key(&packets, &[]));
+    assert_eq!(10 * 14 * 19, decoder_key(&packets, &[make_packet("[[2]]"), make_packet("[[6]]"), make_packet("[10]")]));
+  }
+
+  #[test]
+  fn test_explain_cmp() {
+    let expected = vec![
+      "== Pair 1 ==",
+      "- Compare [1,1,3,1,1] vs [1,1,5,1,1]",
+      "  - Compare 1 vs 1",
+      "  - Compare 1 vs 1",
+      "  - Compare 3 vs 5",
+      "    - Left side is smaller, so input is in the right order",
+      "",
+      "== Pair 2 ==",
+      "- Compare [[1],[2,3,4]] vs [[1],4]",
+      "  - Compare [1] vs [1]",
+      "    - Compare 1 vs 1",
+      "  - Compare [2,3,4] vs 4",
+      "    - Mixed types; convert right to [4] and retry comparison",
+      "    - Compare [2,3,4] vs [4]",
+      "      - Compare 2 vs 4",
+      "        - Left side is smaller, so input is in the right order",
+      "",
+      "== Pair 3 ==",
+      "- Compare [9] vs [[8,7,6]]",
+      "  - Compare 9 vs [8,7,6]",
+      "    - Mixed types; convert left to [9] and retry comparison",
+      "    - Compare [9] vs [8,7,6]",
+      "      - Compare 9 vs 8",
+      "        - Right side is smaller, so input is not in the right order",
+      "",
+      "== Pair 4 ==",
+      "- Compare [[4,4],4,4] vs [[4,4],4,4,4]",
+      "  - Compare [4,4] vs [4,4]",
+      "    - Compare 4 vs 4",
+      "    - Compare 4 vs 4",
+      "  - Compare 4 vs 4",
+      "  - Compare 4 vs 4",
+      "  - Left side ran out of items, so inputs are in the right order",
+      "",
+    ].join("\n");
+    let packets = generator(input().as_str());
+    assert!(explain_pairs(&packets).starts_with(&expected));
+
+    for pair in packets.chunks(2) {
+      assert_eq!(pair[0].cmp(&pair[1]), pair[0].explain_cmp(&pair[1]).ordering);
+      assert_eq!(pair[1].cmp(&pair[0]), pair[1].explain_cmp(&pair[0]).ordering);
+    }
+    assert_eq!(Some(vec![2]), packets[0].explain_cmp(&packets[1]).decided_at);
+    assert_eq!(Some(vec![1, 0]), packets[2].explain_cmp(&packets[3]).decided_at);
+    assert_eq!(Some(vec![0, 0]), packets[4].explain_cmp(&packets[5]).decided_at);
+    assert_eq!(Some(vec![3]), packets[6].explain_cmp(&packets[7]).decided_at);
+    assert_eq!(Some(vec![0]), packets[10].explain_cmp(&packets[11]).decided_at);
+    assert_eq!(Some(vec![1, 1, 1, 1, 2]), packets[14].explain_cmp(&packets[15]).decided_at);
+
+    let same = packets[0].explain_cmp(&packets[0]);
+    assert_eq!(Ordering::Equal, same.ordering);
+    assert_eq!(None, same.decided_at);
+    assert_eq!(6, same.steps.len());
+  }
 }
@@ -52,10 +52,9 @@ impl Display for Tile {
 
 #[derive(Clone)]
 pub struct Cave {
-  sand_source: Position,
+  origin: Position,
   map: Vec<Vec<Tile>>,
   height: i32,
-  width: i32,
 }
 
 impl Cave {
@@ -75,58 +74,206 @@ impl Cave {
     map[sand_source.y as usize][sand_source.x as usize] = SOURCE;
 
     Cave {
-      sand_source,
+      origin: min_pos,
       map,
       height,
-      width,
     }
   }
 
-  fn fill_sand(&mut self) -> bool {
-    let Position { x: mut c_x, y: mut c_y } = self.sand_source;
+  /// The source of sand in the puzzle
+  pub fn default_source() -> Position {
+    Position { x: SAND_SOURCE_X, y: SAND_SOURCE_Y }
+  }
+
+  /// The floor of the puzzle, two below the lowest rock
+  pub fn floor(&self) -> i32 {
+    self.origin.y + self.height + 1
+  }
+
+  fn rocks(&self) -> impl Iterator<Item=Position> + '_ {
+    self.map.iter().enumerate().flat_map(move |(y, row)| {
+      row.iter().enumerate().filter(|(_, t)| **t == ROCK)
+        .map(move |(x, _)| Position { x: x as i32 + self.origin.x, y: y as i32 + self.origin.y })
+    })
+  }
+}
+
+impl Display for Cave {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    let mut output = String::new();
+    for line in self.map.iter() {
+      for tile in line.iter() {
+        output.push(tile.to_char());
+      }
+      output.push('\n');
+    }
+    write!(f, "{output}")
+  }
+}
+
+/// What became of a grain of sand
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Grain {
+  /// Came to rest at the position
+  Settled(Position),
+  /// Fell out of the cave with no floor to stop it
+  Abyss,
+  /// Every source is covered in sand, so no grain could be dropped
+  Blocked,
+}
+
+/// Sand pouring into a cave from any number of sources, taking turns, optionally onto an endless
+/// floor at the given depth
+#[derive(Clone)]
+pub struct SandSimulation {
+  /// The position of the top left tile of the map
+  origin: Position,
+  map: Vec<Vec<Tile>>,
+  width: i32,
+  height: i32,
+  sources: Vec<Position>,
+  floor: Option<i32>,
+  next_source: usize,
+  settled: u32,
+}
+
+impl SandSimulation {
+  pub fn new(cave: &Cave, sources: Vec<Position>, floor: Option<i32>) -> SandSimulation {
+    let rocks: Vec<Position> = cave.rocks().filter(|r| floor.is_none_or(|f| r.y < f)).collect();
+    let all = || rocks.iter().chain(sources.iter());
+    let min_y = all().map(|p| p.y).min().unwrap_or(0);
+    let (mut min_x, mut max_x) = (all().map(|p| p.x).min().unwrap_or(0), all().map(|p| p.x).max().unwrap_or(0));
+    let max_y = match floor {
+      Some(floor) => {
+        // Wide enough for the heaps to reach the floor without spilling over the sides
+        for s in &sources {
+          min_x = min_x.min(s.x - (floor - s.y));
+          max_x = max_x.max(s.x + (floor - s.y));
+        }
+        floor - 1
+      }
+      None => all().map(|p| p.y).max().unwrap_or(0),
+    };
+    let origin = Position { x: min_x, y: min_y };
+    let (width, height) = (max_x - min_x + 1, (max_y - min_y + 1).max(0));
+    let mut map = vec![vec![AIR; width as usize]; height as usize];
+    for r in rocks {
+      map[(r.y - min_y) as usize][(r.x - min_x) as usize] = ROCK;
+    }
+    let sources = sources.iter().map(|s| Position { x: s.x - min_x, y: s.y - min_y }).collect();
+    SandSimulation { origin, map, width, height, sources, floor, next_source: 0, settled: 0 }
+  }
+
+  /// The puzzle's source, with the floor for part 2
+  pub fn puzzle(cave: &Cave, with_floor: bool) -> SandSimulation {
+    SandSimulation::new(cave, vec![Cave::default_source()], with_floor.then(|| cave.floor()))
+  }
 
+  pub fn settled(&self) -> u32 {
+    self.settled
+  }
+
+  fn is_air(&self, x: i32, y: i32) -> bool {
+    x < 0 || x >= self.width || y >= self.height || self.map[y as usize][x as usize] == AIR
+  }
+
+  /// Drop one grain from the next source that is not blocked and follow it until it comes to rest
+  pub fn drop_grain(&mut self) -> Grain {
+    let source = (0..self.sources.len())
+      .map(|i| (self.next_source + i) % self.sources.len())
+      .find(|&i| {
+        let s = &self.sources[i];
+        s.y < self.height && self.is_air(s.x, s.y)
+      });
+    let Some(source) = source else { return Grain::Blocked };
+    self.next_source = (source + 1) % self.sources.len();
+    let Position { x: mut c_x, y: mut c_y } = self.sources[source];
     loop {
-      if c_x == 0 // Opening on left
-        || c_x + 1 == self.width // Opening right
-        || c_y + 1 == self.height { // Opening below
-        return false;
+      if c_x < 0 || c_x >= self.width || c_y + 1 >= self.height && self.floor.is_none() {
+        return Grain::Abyss;
+      }
+      if c_y + 1 == self.height {
+        // Resting on the floor
+        break;
       }
-      // Move left
-      if &self.map[(c_y + 1) as usize][c_x as usize] == &AIR {
+      if self.is_air(c_x, c_y + 1) {
         c_y += 1;
-      } else if &self.map[(c_y + 1) as usize][(c_x - 1) as usize] == &AIR {
+      } else if self.is_air(c_x - 1, c_y + 1) {
         c_x -= 1;
         c_y += 1;
-      } else if &self.map[(c_y + 1) as usize][(c_x + 1) as usize] == &AIR {
+      } else if self.is_air(c_x + 1, c_y + 1) {
         c_x += 1;
         c_y += 1;
       } else {
-        self.map[c_y as usize][c_x as usize] = SAND;
-        return true;
+        break;
+      }
+    }
+    self.map[c_y as usize][c_x as usize] = SAND;
+    self.settled += 1;
+    Grain::Settled(Position { x: c_x + self.origin.x, y: c_y + self.origin.y })
+  }
+
+  /// Drop grains until one falls into the abyss or every source is blocked, returning how many
+  /// have settled in total
+  pub fn run(&mut self) -> u32 {
+    while let Grain::Settled(_) = self.drop_grain() {}
+    self.settled
+  }
+
+  /// How many grains settle when there is a floor, without dropping them one by one. Every air tile
+  /// below a source that is reachable by falling ends up covered in sand, so the heaps are swept a
+  /// row at a time.
+  pub fn fast_count(&self) -> Option<u32> {
+    self.floor?;
+    let width = self.width as usize;
+    let mut prev = vec![false; width];
+    let mut curr = vec![false; width];
+    let mut times = 0;
+    for y in 0..self.height as usize {
+      for x in 0..width {
+        curr[x] = self.map[y][x] != ROCK
+          && (self.map[y][x] == SAND
+          || self.sources.iter().any(|s| s.x as usize == x && s.y as usize == y)
+          || prev[x]
+          || x > 0 && prev[x - 1]
+          || x + 1 < width && prev[x + 1]);
+        if curr[x] {
+          times += 1;
+        }
       }
+      std::mem::swap(&mut prev, &mut curr);
     }
+    Some(times)
   }
 }
 
-impl Display for Cave {
+impl Display for SandSimulation {
   fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
     let mut output = String::new();
-    for line in self.map.iter() {
-      for tile in line.iter() {
-        output.push(tile.to_char());
+    for (y, line) in self.map.iter().enumerate() {
+      for (x, tile) in line.iter().enumerate() {
+        if tile == &AIR && self.sources.iter().any(|s| s.x as usize == x && s.y as usize == y) {
+          output.push(SOURCE.to_char());
+        } else {
+          output.push(tile.to_char());
+        }
       }
       output.push('\n');
     }
+    if self.floor.is_some() {
+      output.push_str(&ROCK.to_char().to_string().repeat(self.width as usize));
+      output.push('\n');
+    }
     write!(f, "{output}")
   }
 }
 
 type Line = (Position, Position);
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
 pub struct Position {
-  x: i32,
-  y: i32,
+  pub x: i32,
+  pub y: i32,
 }
 
 impl Position {
@@ -140,43 +287,18 @@ impl Position {
 }
 
 pub fn part1(cave: &Cave) -> u32 {
-  let mut cave = cave.clone();
-  let mut times = 0;
-  while cave.fill_sand() {
-    times += 1;
-  }
-  times
+  SandSimulation::puzzle(cave, false).run()
 }
 
 pub fn part2(cave: &Cave) -> u32 {
-  let height: i32 = cave.height + 1;
-  let width: i32 = ((2 * height) + 1) as i32;
-  let mut map = vec![vec![AIR; width as usize]; height as usize];
-  let sand_source = Position { x: (width / 2) as i32, y: 0 };
-  map[sand_source.y as usize][sand_source.x as usize] = SAND;
-  let x_adjust = cave.sand_source.x - sand_source.x;
-  let mut times = 1;
-  for y in 1..height {
-    for x in (sand_source.x - y)..=(sand_source.x + y) {
-      let cave_pos_air = y >= cave.height
-        || (x + x_adjust) < 0
-        || (x + x_adjust) >= cave.width
-        || &cave.map[y as usize][(x + x_adjust) as usize] == &AIR;
-      if cave_pos_air
-        && (&map[(y - 1) as usize][x as usize] == &SAND
-          || (x - 1) >= 0 && &map[(y - 1) as usize][(x - 1) as usize] == &SAND
-          || (x + 1) < width && &map[(y - 1) as usize][(x + 1) as usize] == &SAND) {
-        map[y as usize][x as usize] = SAND;
-        times += 1
-      }
-    }
-  }
-  times
+  SandSimulation::puzzle(cave, true).fast_count().unwrap()
 }
 
 #[cfg(test)]
 mod tests {
-  use crate::day14::{generator, part1, part2};
+  use rand::{Rng, SeedableRng};
+  use rand::rngs::StdRng;
+  use crate::day14::{Cave, generator, Grain, part1, part2, Position, SandSimulation};
 
   fn input() -> String {
     vec![
@@ -203,8 +325,9 @@ mod tests {
 
   #[test]
   fn test_fills() {
-    let mut cave = generator(input().as_str());
-    assert_eq!(true, cave.fill_sand());
+    let cave = generator(input().as_str());
+    let mut cave = SandSimulation::puzzle(&cave, false);
+    assert_eq!(Grain::Settled(Position { x: 500, y: 8 }), cave.drop_grain());
     let expected = "......+...\n\
                     ..........\n\
                     ..........\n\
@@ -216,7 +339,7 @@ mod tests {
                     ......o.#.\n\
                     #########.\n";
     assert_eq!(expected, cave.to_string());
-    assert_eq!(true, cave.fill_sand());
+    assert_eq!(Grain::Settled(Position { x: 499, y: 8 }), cave.drop_grain());
     let expected = "......+...\n\
                     ..........\n\
                     ..........\n\
@@ -228,9 +351,9 @@ mod tests {
                     .....oo.#.\n\
                     #########.\n";
     assert_eq!(expected, cave.to_string());
-    assert_eq!(true, cave.fill_sand());
-    assert_eq!(true, cave.fill_sand());
-    assert_eq!(true, cave.fill_sand());
+    assert_eq!(Grain::Settled(Position { x: 501, y: 8 }), cave.drop_grain());
+    assert_eq!(Grain::Settled(Position { x: 500, y: 7 }), cave.drop_grain());
+    assert_eq!(Grain::Settled(Position { x: 498, y: 8 }), cave.drop_grain());
     let expected = "......+...\n\
                     ..........\n\
                     ..........\n\
@@ -244,7 +367,7 @@ mod tests {
     assert_eq!(expected, cave.to_string());
 
     for _ in 5..22 {
-      cave.fill_sand();
+      cave.drop_grain();
     }
     let expected = "......+...\n\
                     ..........\n\
@@ -258,8 +381,8 @@ mod tests {
                     #########.\n";
     assert_eq!(expected, cave.to_string());
 
-    assert_eq!(true, cave.fill_sand());
-    assert_eq!(true, cave.fill_sand());
+    assert!(matches!(cave.drop_grain(), Grain::Settled(_)));
+    assert!(matches!(cave.drop_grain(), Grain::Settled(_)));
     let expected = "......+...\n\
                     ..........\n\
                     ......o...\n\
@@ -271,8 +394,9 @@ mod tests {
                     .o.ooooo#.\n\
                     #########.\n";
     assert_eq!(expected, cave.to_string());
-    assert_eq!(false, cave.fill_sand());
+    assert_eq!(Grain::Abyss, cave.drop_grain());
     assert_eq!(expected, cave.to_string());
+    assert_eq!(24, cave.settled());
   }
 
   #[test]
@@ -286,4 +410,69 @@ mod tests {
     let cave = generator(input().as_str());
     assert_eq!(93, part2(&cave));
   }
-}
\ No newline at end of file
+
+  #[test]
+  fn test_floor() {
+    let cave = generator(input().as_str());
+    let mut sim = SandSimulation::puzzle(&cave, true);
+    assert_eq!(11, cave.floor());
+    assert_eq!(Some(93), sim.fast_count());
+    assert_eq!(93, sim.run());
+    assert_eq!(Grain::Blocked, sim.drop_grain());
+    assert_eq!(Some(93), sim.fast_count());
+    assert_eq!(None, SandSimulation::puzzle(&cave, false).fast_count());
+    let drawing = sim.to_string();
+    assert_eq!("...........o...........", drawing.lines().next().unwrap());
+    assert_eq!("#######################", drawing.lines().last().unwrap());
+  }
+
+  #[test]
+  fn test_sources() {
+    let cave = generator(input().as_str());
+    let sources = vec![Position { x: 500, y: 0 }, Position { x: 497, y: 3 }];
+    let mut sim = SandSimulation::new(&cave, sources.clone(), None);
+    assert_eq!(Grain::Settled(Position { x: 500, y: 8 }), sim.drop_grain());
+    assert_eq!(Grain::Settled(Position { x: 497, y: 5 }), sim.drop_grain());
+    assert_eq!(Grain::Settled(Position { x: 499, y: 8 }), sim.drop_grain());
+    assert_eq!(Grain::Settled(Position { x: 495, y: 8 }), sim.drop_grain());
+    let settled = sim.run();
+    assert!(settled > 4);
+    assert_eq!(settled, sim.settled());
+
+    let mut sim = SandSimulation::new(&cave, sources, Some(11));
+    assert_eq!(sim.fast_count(), Some(sim.run()));
+  }
+
+  fn random_cave(rng: &mut StdRng) -> Cave {
+    let lines: Vec<String> = (0..rng.gen_range(1..6)).map(|_| {
+      let mut x = rng.gen_range(485..515);
+      let mut y = rng.gen_range(3..25);
+      let mut points = vec![format!("{x},{y}")];
+      for i in 0..rng.gen_range(1..4) {
+        if i % 2 == 0 {
+          x = (x + rng.gen_range(-6..=6)).clamp(480, 520);
+        } else {
+          y = (y + rng.gen_range(-6..=6)).clamp(3, 30);
+        }
+        points.push(format!("{x},{y}"));
+      }
+      points.join(" -> ")
+    }).collect();
+    generator(lines.join("\n").as_str())
+  }
+
+  #[test]
+  fn test_modes_agree() {
+    let mut rng = StdRng::seed_from_u64(14);
+    for _ in 0..100 {
+      let cave = random_cave(&mut rng);
+      let sources: Vec<Position> = (0..rng.gen_range(1..4))
+        .map(|_| Position { x: rng.gen_range(490..510), y: rng.gen_range(0..3) })
+        .collect();
+      let mut sim = SandSimulation::new(&cave, sources, Some(cave.floor() + rng.gen_range(0..3)));
+      let fast = sim.fast_count();
+      assert_eq!(fast, Some(sim.run()));
+      assert_eq!(Grain::Blocked, sim.drop_grain());
+    }
+  }
+}
@@ -1,5 +1,7 @@
 use std::cmp::Reverse;
 use std::collections::{HashMap, VecDeque};
+use std::fmt::Write;
+use std::rc::Rc;
 
 pub fn generator(input: &str) -> Puzzle {
   let (valves, mut distances, start) = parse(input);
@@ -161,42 +163,65 @@ impl Puzzle {
     }
   }
   fn compute_max_pressure(&self, until_time: u32) -> u32 {
-    let mut max_pressure = 0;
-    let mut stack: Vec<(u32, u32, Entry)> = Vec::new();
+    self.search::<()>(until_time).pressure
+  }
+
+  fn compute_max_pressure_with_helper(&self, until_time: u32) -> u32 {
+    self.search_with_helper::<()>(until_time).pressure
+  }
+
+  /// The code of the valve with the given id, as used in the input
+  pub fn valve_code(&self, id: u32) -> &str {
+    &self.valves[id as usize].code
+  }
+
+  /// The best plan for opening valves alone within the given time
+  pub fn best_plan(&self, until_time: u32) -> Plan {
+    Plan::from_entry(&self.search(until_time), 1)
+  }
+
+  /// The best plan for opening valves together with the elephant within the given time
+  pub fn best_plan_with_helper(&self, until_time: u32) -> Plan {
+    Plan::from_entry(&self.search_with_helper(until_time), 2)
+  }
+
+  fn search<T: Trail>(&self, until_time: u32) -> Entry<T> {
+    let mut best = Entry::new(self.pressure_valves);
+    let mut stack: Vec<(u32, u32, Entry<T>)> = Vec::new();
     stack.push((self.start, 0, Entry::new(self.pressure_valves)));
     while !stack.is_empty() {
       let (prev, time, entry) = stack.pop().unwrap();
-      if entry.max_pressure_ignoring_travel(time, until_time, self) < max_pressure {
+      if entry.max_pressure_ignoring_travel(time, until_time, self) < best.pressure {
         continue;
       }
       let stack_size = stack.len();
       for i in (0..self.pressure_valves).rev() {
-        if let Some(e) = process_entry(prev, time, &entry, i, until_time, &self) {
+        if let Some(e) = process_entry(prev, time, &entry, i, 0, until_time, self) {
           stack.push(e);
         }
       }
-      if stack_size == stack.len() {
+      if stack_size == stack.len() && entry.pressure > best.pressure {
         // No entries added
-        max_pressure = max_pressure.max(entry.pressure);
+        best = entry;
       }
     }
-    max_pressure
+    best
   }
 
-  fn compute_max_pressure_with_helper(&self, until_time: u32) -> u32 {
-    let mut max_pressure = 0;
-    let mut stack: Vec<((u32, u32), (u32, u32), Entry)> = Vec::new();
+  fn search_with_helper<T: Trail>(&self, until_time: u32) -> Entry<T> {
+    let mut best = Entry::new(self.pressure_valves);
+    let mut stack: Vec<PairState<T>> = Vec::new();
     stack.push(((self.start, 0), (self.start, 0), Entry::new(self.pressure_valves)));
     while !stack.is_empty() {
       let ((prev1, time1), (prev2, time2), entry) = stack.pop().unwrap();
-      if entry.max_pressure_ignoring_travel_with_helper(time1, time2, until_time, self) < max_pressure {
+      if entry.max_pressure_ignoring_travel_with_helper(time1, time2, until_time, self) < best.pressure {
         continue;
       }
       let stack_size = stack.len();
       for i in (0..self.pressure_valves).rev() {
         // Move the one closest to it
-        let r1 = process_entry(prev1, time1, &entry, i, until_time, &self);
-        let r2 = process_entry(prev2, time2, &entry, i, until_time, &self);
+        let r1 = process_entry(prev1, time1, &entry, i, 0, until_time, self);
+        let r2 = process_entry(prev2, time2, &entry, i, 1, until_time, self);
 
         if r1.is_some() && self.distances[prev1 as usize][i as usize] < self.distances[prev2 as usize][i as usize] {
           let (p, t, e) = r1.unwrap();
@@ -206,17 +231,191 @@ impl Puzzle {
           stack.push(((prev1, time1), (p, t), e));
         }
       }
-      if stack_size == stack.len() {
+      if stack_size == stack.len() && entry.pressure > best.pressure {
         // No entries added
-        max_pressure = max_pressure.max(entry.pressure);
+        best = entry;
+      }
+    }
+    best
+  }
+
+  /// Render the tunnels as parsed from the input as a Graphviz DOT graph. When a plan is
+  /// given, the opened valves are labelled with their opening order and the tunnels walked
+  /// between them are highlighted in the colour of the actor that walked them.
+  pub fn tunnels_to_dot(&self, plan: Option<&Plan>) -> String {
+    let count = self.valves.len();
+    let mut walked: HashMap<(u32, u32), usize> = HashMap::new();
+    if let Some(plan) = plan {
+      for (actor, route) in plan.routes.iter().enumerate() {
+        let mut prev = self.start;
+        for opening in route {
+          let path = self.tunnel_path(prev, opening.valve);
+          for step in path.windows(2) {
+            walked.insert(edge_key(step[0], step[1]), actor);
+          }
+          prev = opening.valve;
+        }
+      }
+    }
+    let mut dot = String::new();
+    writeln!(dot, "graph tunnels {{").unwrap();
+    writeln!(dot, "  node [shape=circle];").unwrap();
+    for valve in &self.valves {
+      self.write_dot_valve(&mut dot, valve, plan);
+    }
+    for from in 0..count as u32 {
+      for to in (from + 1)..count as u32 {
+        if self.distances[from as usize][to as usize] != 1 {
+          continue;
+        }
+        write!(dot, "  {} -- {}", self.valves[from as usize].code,
+               self.valves[to as usize].code).unwrap();
+        match walked.get(&edge_key(from, to)) {
+          Some(actor) => writeln!(dot, " [color={}, penwidth=3];", actor_colour(*actor)).unwrap(),
+          None => writeln!(dot, ";").unwrap(),
+        }
+      }
+    }
+    writeln!(dot, "}}").unwrap();
+    dot
+  }
+
+  /// Render the compressed graph the solver works on as a Graphviz DOT graph: only the
+  /// valves with a flow rate plus the start, with edges weighted by travel time. When a
+  /// plan is given, the opened valves are labelled with their opening order and the hops
+  /// between them are highlighted in the colour of the actor that made them.
+  pub fn compressed_to_dot(&self, plan: Option<&Plan>) -> String {
+    let mut nodes: Vec<u32> = (0..self.pressure_valves).collect();
+    if self.start >= self.pressure_valves {
+      nodes.push(self.start);
+    }
+    let mut hops: HashMap<(u32, u32), usize> = HashMap::new();
+    if let Some(plan) = plan {
+      for (actor, route) in plan.routes.iter().enumerate() {
+        let mut prev = self.start;
+        for opening in route {
+          hops.insert(edge_key(prev, opening.valve), actor);
+          prev = opening.valve;
+        }
+      }
+    }
+    let mut dot = String::new();
+    writeln!(dot, "graph compressed {{").unwrap();
+    writeln!(dot, "  node [shape=circle];").unwrap();
+    for &id in &nodes {
+      self.write_dot_valve(&mut dot, &self.valves[id as usize], plan);
+    }
+    for (i, &from) in nodes.iter().enumerate() {
+      for &to in &nodes[i + 1..] {
+        let distance = self.distances[from as usize][to as usize];
+        if distance == u32::MAX {
+          continue;
+        }
+        write!(dot, "  {} -- {} [label={}", self.valves[from as usize].code,
+               self.valves[to as usize].code, distance).unwrap();
+        match hops.get(&edge_key(from, to)) {
+          Some(actor) => writeln!(dot, ", color={}, penwidth=3];", actor_colour(*actor)).unwrap(),
+          None => writeln!(dot, ", color=gray];").unwrap(),
+        }
       }
     }
-    max_pressure
+    writeln!(dot, "}}").unwrap();
+    dot
+  }
+
+  fn write_dot_valve(&self, dot: &mut String, valve: &Valve, plan: Option<&Plan>) {
+    let opening = plan.and_then(|p| p.position(valve.id));
+    write!(dot, "  {} [label=\"{}\\n{}", valve.code, valve.code, valve.rate).unwrap();
+    if let Some((actor, order, opening)) = opening {
+      write!(dot, "\\n#{} @{}\", style=filled, fillcolor={}", order + 1, opening.time,
+             actor_colour(actor)).unwrap();
+    } else {
+      write!(dot, "\"").unwrap();
+    }
+    if valve.id == self.start {
+      write!(dot, ", shape=doublecircle").unwrap();
+    }
+    writeln!(dot, "];").unwrap();
   }
+
+  /// Shortest walk through the tunnels between two valves, including both ends
+  fn tunnel_path(&self, from: u32, to: u32) -> Vec<u32> {
+    let count = self.valves.len();
+    let mut came_from: Vec<Option<u32>> = vec![None; count];
+    let mut queue = VecDeque::new();
+    came_from[from as usize] = Some(from);
+    queue.push_back(from);
+    while let Some(curr) = queue.pop_front() {
+      if curr == to {
+        break;
+      }
+      for next in 0..count as u32 {
+        if self.distances[curr as usize][next as usize] == 1 && came_from[next as usize].is_none() {
+          came_from[next as usize] = Some(curr);
+          queue.push_back(next);
+        }
+      }
+    }
+    let mut path = vec![to];
+    let mut curr = to;
+    while curr != from {
+      curr = came_from[curr as usize].expect("Expecting valves to be connected");
+      path.push(curr);
+    }
+    path.reverse();
+    path
+  }
+}
+
+fn edge_key(a: u32, b: u32) -> (u32, u32) {
+  (a.min(b), a.max(b))
+}
+
+fn actor_colour(actor: usize) -> &'static str {
+  const COLOURS: [&str; 2] = ["orange", "lightblue"];
+  COLOURS[actor % COLOURS.len()]
+}
+
+/// A valve opened by the solver and the minute at which it starts releasing pressure
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Opening {
+  pub valve: u32,
+  pub time: u32,
+}
+
+/// The order in which each actor opens valves and the pressure that releases
+#[derive(Debug, Eq, PartialEq)]
+pub struct Plan {
+  pub pressure: u32,
+  pub routes: Vec<Vec<Opening>>,
 }
 
-fn process_entry(prev: u32, time: u32, entry: &Entry, to: u32, until_time: u32, puzzle: &Puzzle)
-                 -> Option<(u32, u32, Entry)> {
+impl Plan {
+  fn from_entry(entry: &Entry<Opened>, actors: usize) -> Plan {
+    let mut routes = vec![Vec::new(); actors];
+    let mut step = entry.opened.clone();
+    while let Some(s) = step {
+      routes[s.actor].push(Opening { valve: s.valve, time: s.time });
+      step = s.prev.clone();
+    }
+    for route in routes.iter_mut() {
+      route.reverse();
+    }
+    Plan { pressure: entry.pressure, routes }
+  }
+
+  /// The actor, position in its route and opening for the given valve if it is opened
+  fn position(&self, valve: u32) -> Option<(usize, usize, &Opening)> {
+    self.routes.iter().enumerate().find_map(|(actor, route)| {
+      route.iter().enumerate()
+           .find(|(_, o)| o.valve == valve)
+           .map(|(order, o)| (actor, order, o))
+    })
+  }
+}
+
+fn process_entry<T: Trail>(prev: u32, time: u32, entry: &Entry<T>, to: u32, actor: usize,
+                           until_time: u32, puzzle: &Puzzle) -> Option<(u32, u32, Entry<T>)> {
   if !entry.remaining.is_set(to as u32) {
     return None;
   }
@@ -225,20 +424,51 @@ fn process_entry(prev: u32, time: u32, entry: &Entry, to: u32, until_time: u32,
     let mut entry = entry.clone();
     entry.remaining.unset(to);
     entry.pressure += (until_time - t) * puzzle.valves[to as usize].rate;
+    entry.opened.record(to, t, actor);
     Some((to, t, entry))
   } else {
     None
   }
 }
 
+/// Records the valves opened while searching. The searches behind `part1` and `part2` only
+/// need the pressure, so they use `()` and pay nothing for it.
+trait Trail: Clone + Default {
+  fn record(&mut self, valve: u32, time: u32, actor: usize);
+}
+
+impl Trail for () {
+  fn record(&mut self, _valve: u32, _time: u32, _actor: usize) {}
+}
+
+/// Opened valves are kept as a shared linked list so that cloning entries stays cheap
+struct Step {
+  valve: u32,
+  time: u32,
+  actor: usize,
+  prev: Opened,
+}
+
+type Opened = Option<Rc<Step>>;
+
+impl Trail for Opened {
+  fn record(&mut self, valve: u32, time: u32, actor: usize) {
+    *self = Some(Rc::new(Step { valve, time, actor, prev: self.take() }));
+  }
+}
+
+/// The last valve opened and when for each of the two actors, and the entry so far
+type PairState<T> = ((u32, u32), (u32, u32), Entry<T>);
+
 #[derive(Clone)]
-struct Entry {
+struct Entry<T: Trail = ()> {
   pressure: u32,
   remaining: BitMap,
+  opened: T,
 }
 
-impl Entry {
-  fn new(pressure_valves: u32) -> Entry {
+impl<T: Trail> Entry<T> {
+  fn new(pressure_valves: u32) -> Entry<T> {
     let mut map: u64 = 0;
     for _ in 0..pressure_valves {
       map <<= 1;
@@ -248,6 +478,7 @@ impl Entry {
     Entry {
       pressure: 0,
       remaining: BitMap { map },
+      opened: T::default(),
     }
   }
   fn max_pressure_ignoring_travel(&self, time: u32, until_time: u32, puzzle: &Puzzle) -> u32 {
@@ -295,7 +526,7 @@ impl Entry {
 
 #[cfg(test)]
 mod tests {
-  use crate::day16::{BitMap, compute_distances, Entry, generator, parse, part2, Valve};
+  use crate::day16::{BitMap, compute_distances, Entry, generator, Opening, parse, part2, Valve};
 
   fn input() -> String {
     vec![
@@ -376,7 +607,7 @@ mod tests {
       map <<= 1;
       map += 1;
     }
-    let entry = Entry { pressure: 0, remaining: BitMap { map } };
+    let entry = Entry { pressure: 0, remaining: BitMap { map }, opened: () };
     assert_eq!(2024, entry.max_pressure_ignoring_travel(0, 30, &puzzle));
     assert_eq!(1651, puzzle.compute_max_pressure(30));
   }
@@ -389,8 +620,55 @@ mod tests {
       map <<= 1;
       map += 1;
     }
-    let entry = Entry { pressure: 0, remaining: BitMap { map } };
+    let entry = Entry { pressure: 0, remaining: BitMap { map }, opened: () };
     assert_eq!(1858, entry.max_pressure_ignoring_travel_with_helper(0, 0, 26, &puzzle));
     assert_eq!(1707, part2(&puzzle));
   }
-}
\ No newline at end of file
+
+  #[test]
+  fn test_best_plan() {
+    let puzzle = generator(input().as_str());
+    let code = |o: &Opening| puzzle.valve_code(o.valve);
+    let plan = puzzle.best_plan(30);
+    assert_eq!(1651, plan.pressure);
+    assert_eq!(vec!["DD", "BB", "JJ", "HH", "EE", "CC"],
+               plan.routes[0].iter().map(code).collect::<Vec<&str>>());
+    assert_eq!(vec![2, 5, 9, 17, 21, 24],
+               plan.routes[0].iter().map(|o| o.time).collect::<Vec<u32>>());
+
+    let plan = puzzle.best_plan_with_helper(26);
+    assert_eq!(1707, plan.pressure);
+    let mut routes: Vec<Vec<&str>> = plan.routes.iter()
+                                         .map(|r| r.iter().map(code).collect())
+                                         .collect();
+    routes.sort();
+    assert_eq!(vec![vec!["DD", "HH", "EE"], vec!["JJ", "BB", "CC"]], routes);
+  }
+
+  #[test]
+  fn test_dot() {
+    let puzzle = generator(input().as_str());
+    let plan = puzzle.best_plan(30);
+
+    let dot = puzzle.tunnels_to_dot(None);
+    assert!(dot.starts_with("graph tunnels {\n"));
+    assert!(dot.contains("  AA [label=\"AA\\n0\", shape=doublecircle];\n"));
+    assert_eq!(10, dot.matches(" -- ").count());
+    assert!(!dot.contains("penwidth"));
+
+    let dot = puzzle.tunnels_to_dot(Some(&plan));
+    assert!(dot.contains("  DD [label=\"DD\\n20\\n#1 @2\", style=filled, fillcolor=orange];\n"));
+    // The route walks every tunnel at least once
+    assert_eq!(10, dot.matches("penwidth=3").count());
+    assert!(dot.contains("  HH -- GG [color=orange, penwidth=3];\n"));
+    assert!(dot.ends_with("}\n"));
+
+    let dot = puzzle.compressed_to_dot(Some(&plan));
+    assert!(dot.starts_with("graph compressed {\n"));
+    assert!(!dot.contains("  FF "));
+    assert!(dot.contains("  AA [label=\"AA\\n0\", shape=doublecircle];\n"));
+    // Six flowing valves plus the start are fully connected
+    assert_eq!(21, dot.matches(" -- ").count());
+    assert_eq!(6, dot.matches("penwidth=3").count());
+  }
+}
@@ -1,35 +1,125 @@
-use std::collections::{HashMap};
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::{fs, io};
+use std::path::Path;
+use serde::{Deserialize, Serialize};
 
 const CHAMBER_WIDTH: u8 = 7;
+const MAX_CHAMBER_WIDTH: u8 = 64;
 const STARTS_ABOVE: u8 = 3;
+const STARTS_LEFT: u8 = 2;
 const DIRECTION_LEFT: char = '<';
 const DIRECTION_RIGHT: char = '>';
-const _MOST_SIG_BIT_SET: u8 = 1 << 6;
+
+/// The rocks of the puzzle, from the top row down, separated by blank lines
+pub const PUZZLE_ROCKS: &str = "\
+####
+
+.#.
+###
+.#.
+
+..#
+..#
+###
+
+#
+#
+#
+#
+
+##
+##";
 
 pub fn generator(input: &str) -> Puzzle {
   Puzzle::new(input.lines().next().unwrap().trim().chars().collect())
 }
 
-pub fn part1(puzzle: &Puzzle) -> u64 {
-  let mut simulation = Simulation::new(puzzle.clone());
-  simulation.run(2022) as u64
+pub fn part1(puzzle: &Puzzle) -> u128 {
+  HeightSeries::new(puzzle).height(2022)
 }
 
 #[derive(Eq, PartialEq, Debug)]
 struct CacheEntry {
   idx: u64,
-  scope: Vec<u8>,
-  change: usize,
+  scope: Vec<Line>,
 }
 
-struct Simulation {
+/// The chamber after some rocks have come to rest
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Tower {
+  pub height: u64,
+  /// For each column from the left, how many rows below the top its highest rock is, or `None`
+  /// when no rock has landed in it
+  pub profile: Vec<Option<usize>>,
+}
+
+/// The height of the tower after any number of rocks, worked out from one simulation that runs
+/// until the rocks start repeating themselves
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct HeightSeries {
+  /// The height after each number of rocks up to the end of the first cycle
+  heights: Vec<u64>,
+  prefix: u64,
+  cycle: u64,
+  gain: u64,
+}
+
+impl HeightSeries {
+  pub fn new(puzzle: &Puzzle) -> HeightSeries {
+    let (heights, cycle) = Simulation::new(puzzle.clone()).detect_cycle(None);
+    let (prefix, cycle) = cycle.expect("The rocks repeat eventually");
+    HeightSeries::from_heights(heights, prefix, cycle)
+  }
+
+  fn from_heights(mut heights: Vec<u64>, prefix: u64, cycle: u64) -> HeightSeries {
+    heights.truncate((prefix + cycle + 1) as usize);
+    let gain = heights[(prefix + cycle) as usize] - heights[prefix as usize];
+    HeightSeries { heights, prefix, cycle, gain }
+  }
+
+  /// How many rocks fall before they start repeating
+  pub fn prefix(&self) -> u64 {
+    self.prefix
+  }
+
+  /// How many rocks there are in each repetition
+  pub fn cycle(&self) -> u64 {
+    self.cycle
+  }
+
+  /// How much taller the tower gets with each repetition
+  pub fn gain(&self) -> u64 {
+    self.gain
+  }
+
+  /// The height after the given number of rocks
+  pub fn height(&self, rock_count: u64) -> u128 {
+    if rock_count < self.heights.len() as u64 {
+      return self.heights[rock_count as usize] as u128;
+    }
+    let cycles = (rock_count - self.prefix) / self.cycle;
+    let offset = (rock_count - self.prefix) % self.cycle;
+    self.heights[(self.prefix + offset) as usize] as u128 + cycles as u128 * self.gain as u128
+  }
+
+  pub fn save(&self, path: &Path) -> io::Result<()> {
+    fs::write(path, serde_json::to_string(self)?)
+  }
+
+  pub fn load(path: &Path) -> io::Result<HeightSeries> {
+    Ok(serde_json::from_str(&fs::read_to_string(path)?)?)
+  }
+}
+
+pub struct Simulation {
   puzzle: Puzzle,
   jet_idx: usize,
   rock_idx: usize,
 }
 
 impl Simulation {
-  fn new(puzzle: Puzzle) -> Simulation {
+  pub fn new(puzzle: Puzzle) -> Simulation {
     Simulation {
       puzzle,
       jet_idx: 0,
@@ -48,55 +138,73 @@ impl Simulation {
     r
   }
 
-  fn run(&mut self, rock_count: u64) -> u64 {
+  /// The height of the tower after the given number of rocks, skipping ahead once the rocks start
+  /// repeating themselves
+  pub fn run(&mut self, rock_count: u64) -> u128 {
+    let (heights, cycle) = self.detect_cycle(Some(rock_count));
+    match cycle {
+      Some((prefix, cycle)) => HeightSeries::from_heights(heights, prefix, cycle).height(rock_count),
+      None => heights[rock_count as usize] as u128,
+    }
+  }
+
+  /// Drop rocks until they start repeating themselves or the limit is reached, returning the
+  /// height after each rock and, when found, where the cycle starts and how many rocks it spans
+  fn detect_cycle(&mut self, limit: Option<u64>) -> (Vec<u64>, Option<(u64, u64)>) {
     self.rock_idx = 0;
     self.jet_idx = 0;
     let mut chamber: Chamber = Vec::new();
+    let mut heights = vec![0];
     let mut repeats: HashMap<(usize, usize), CacheEntry> = HashMap::new();
     let mut max_depth = 0;
-    for i in 0..rock_count {
+    let mut i = 0;
+    while limit.is_none_or(|l| i < l) {
       let curr_key = (self.rock_idx, self.jet_idx);
-      let curr_len = chamber.len();
       let rock = self.next_rock();
       let (scope, new_depth) = self.simulate_rock(rock, &mut chamber);
       if new_depth > max_depth {
         max_depth = new_depth;
         repeats.clear();
       }
-      if let Some(cache_entry) = repeats.get(&curr_key) {
-        if cache_entry.scope == scope {
-          // We have found a repeat so short circuit the result
-          let mut increases: Vec<(u64, usize)> = repeats.values().filter_map(|e| {
-            if e.idx >= cache_entry.idx {
-              Some((e.idx, e.change))
-            } else {
-              None
-            }
-          }).collect();
-          increases.sort_by_key(|e| e.0);
-          let increase_pass: u64 = increases.iter().map(|e| e.1 as u64).sum();
-          let prev_height: u64 = (chamber.len() - cache_entry.change) as u64;
-          let pass_size = i - cache_entry.idx;
-          let remaining = rock_count - i;
-          let remainder = (remaining % pass_size) as usize;
-          let remaining_height_quotient = remaining / pass_size * increase_pass;
-          let remaining_height_remainder: usize = increases[..remainder].iter().map(|e| e.1).sum();
-          return prev_height as u64 + remaining_height_quotient as u64 + remaining_height_remainder as u64;
-        } else {
-          repeats.insert(curr_key, CacheEntry { idx: i, scope, change: chamber.len() - curr_len });
+      match repeats.get(&curr_key) {
+        Some(cache_entry) if cache_entry.scope == scope => {
+          // From here on every rock repeats what the rock of the earlier entry did
+          return (heights, Some((cache_entry.idx, i - cache_entry.idx)));
+        }
+        _ => {
+          repeats.insert(curr_key, CacheEntry { idx: i, scope });
         }
-      } else {
-        repeats.insert(curr_key, CacheEntry { idx: i, scope, change: chamber.len() - curr_len });
       }
+      heights.push(chamber.len() as u64);
+      i += 1;
     }
-    chamber.len() as u64
+    (heights, None)
   }
 
-  fn simulate_rock(&mut self, mut rock: Rock, chamber: &mut Chamber) -> (Vec<u8>, usize) {
-    // As the rock starts 3 above, we will have 4 horizontal movements before we are top of the
-    // existing rock pile
-    for _ in 0..=STARTS_ABOVE {
-      shift_rock(&mut rock, self.next_jet(), false);
+  /// Drop every one of the given number of rocks, giving the height and the shape of the top of
+  /// the tower. Unlike [Simulation::run] this takes time in proportion to the rock count.
+  pub fn tower(&mut self, rock_count: u64) -> Tower {
+    self.rock_idx = 0;
+    self.jet_idx = 0;
+    let mut chamber: Chamber = Vec::new();
+    for _ in 0..rock_count {
+      let rock = self.next_rock();
+      self.simulate_rock(rock, &mut chamber);
+    }
+    let width = self.puzzle.width;
+    let profile = (0..width).map(|col| {
+      let bit: Line = 1 << (width - 1 - col);
+      chamber.iter().rev().position(|l| l & bit != 0)
+    }).collect();
+    Tower { height: chamber.len() as u64, profile }
+  }
+
+  fn simulate_rock(&mut self, mut rock: Rock, chamber: &mut Chamber) -> (Vec<Line>, usize) {
+    // As the rock starts some lines above, it is pushed by one more jet than that before it is
+    // level with the top of the existing rock pile
+    let wall = self.puzzle.wall();
+    for _ in 0..=self.puzzle.spawn.above {
+      shift_rock(&mut rock, self.next_jet(), wall, false);
     }
 
     let mut start = self.handle_into_existing_rocks(&mut rock, chamber);
@@ -125,18 +233,18 @@ impl Simulation {
   fn handle_into_existing_rocks(&mut self, rock: &mut Rock, chamber: &Chamber) -> usize {
     // Start checking the top line
     let mut start = chamber.len();
+    let wall = self.puzzle.wall();
     while start > 0 {
       // Start with the top rock line in chamber
       start -= 1;
-      //println!("down: {allow_down} and shift: {allow_shift}");
       if !simulate_down(rock, chamber, start) {
         // We cannot move down, come to rest at current
         return start + 1;
       }
 
       let d = self.next_jet();
-      if simulate_shift(rock, chamber, start, d) {
-        shift_rock(rock, d, true);
+      if simulate_shift(rock, chamber, start, d, wall) {
+        shift_rock(rock, d, wall, true);
       }
     }
     start
@@ -157,17 +265,17 @@ fn simulate_down(rock: &Rock, chamber: &Chamber, start: usize) -> bool {
   allow_down
 }
 
-fn simulate_shift(rock: &mut Rock, chamber: &Chamber, start: usize, direction: &char) -> bool {
+fn simulate_shift(rock: &mut Rock, chamber: &Chamber, start: usize, direction: &char, wall: Line) -> bool {
   let mut allow_shift = if direction == &DIRECTION_LEFT {
-    rock.iter().all(|l| l.leading_zeros() > 1)
+    rock.iter().all(|l| l & wall == 0)
   } else {
-    rock.iter().all(|l| l.trailing_zeros() > 0)
+    rock.iter().all(|l| l & 1 == 0)
   };
   let mut rock_idx = rock.len();
   // Check if a move is possible
   for c_i in start..(start + rock.len()).min(chamber.len()) {
     rock_idx -= 1;
-    allow_shift &= can_shift(&rock[rock_idx], &chamber[c_i], direction);
+    allow_shift &= can_shift(&rock[rock_idx], &chamber[c_i], direction, wall);
     if !allow_shift {
       break;
     }
@@ -175,35 +283,80 @@ fn simulate_shift(rock: &mut Rock, chamber: &Chamber, start: usize, direction: &
   allow_shift
 }
 
-pub fn part2(puzzle: &Puzzle) -> u64 {
-  let mut simulation = Simulation::new(puzzle.clone());
-  simulation.run(1000000000000)
+pub fn part2(puzzle: &Puzzle) -> u128 {
+  HeightSeries::new(puzzle).height(1000000000000)
+}
+
+/// A row of the chamber with the leftmost column in the highest used bit
+type Line = u64;
+type Chamber = Vec<Line>;
+/// The rows of a rock from the top down
+type Rock = Vec<Line>;
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum ShapeError {
+  NoRocks,
+  UnexpectedChar(char),
+  /// A rock with no `#`
+  EmptyRock(usize),
+  /// A rock that does not fit between the spawn offset and the right wall
+  TooWide(usize),
+  /// A chamber of no columns or more than fit in a line
+  ChamberWidth(u8),
+  NoJets,
+  /// A jet that is neither `<` nor `>`
+  UnexpectedJet(char),
 }
 
-type Line = u8;
-type Chamber = Vec<u8>;
-type Rock = Vec<u8>;
+impl Display for ShapeError {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    match self {
+      ShapeError::NoRocks => write!(f, "no rocks given"),
+      ShapeError::UnexpectedChar(c) => write!(f, "unexpected character {c}"),
+      ShapeError::EmptyRock(idx) => write!(f, "rock {idx} is empty"),
+      ShapeError::TooWide(idx) => write!(f, "rock {idx} does not fit in the chamber"),
+      ShapeError::ChamberWidth(w) => write!(f, "chamber width {w} is not between 1 and {MAX_CHAMBER_WIDTH}"),
+      ShapeError::NoJets => write!(f, "no jets given"),
+      ShapeError::UnexpectedJet(c) => write!(f, "unexpected jet {c}"),
+    }
+  }
+}
 
-fn make_line(input: &str) -> u8 {
-  if input.len() != CHAMBER_WIDTH as usize {
-    panic!("Expect the length of line to be 7 but received {input}");
+/// Read rocks drawn with `#` and `.`, separated by blank lines, placing them the given number of
+/// columns from the left wall
+fn parse_rocks(spec: &str, width: u8, left: u8) -> Result<Vec<Rock>, ShapeError> {
+  if width == 0 || width > MAX_CHAMBER_WIDTH {
+    return Err(ShapeError::ChamberWidth(width));
   }
-  let mut line = 0;
-  for c in input.chars() {
-    line <<= 1;
-    match c {
-      '#' => {
-        line += 1;
+  let rocks: Vec<Rock> = spec.trim_matches('\n').split("\n\n").enumerate().map(|(idx, drawing)| {
+    let mut rock = Vec::new();
+    for row in drawing.lines().map(|l| l.trim_end()) {
+      if row.len() + left as usize > width as usize {
+        return Err(ShapeError::TooWide(idx));
       }
-      '.' => {}
-      _ => panic!("Unexpected character {c}")
+      let mut line: Line = 0;
+      for (col, c) in row.chars().enumerate() {
+        match c {
+          '#' => line |= 1 << (width as usize - 1 - left as usize - col),
+          '.' => {}
+          _ => return Err(ShapeError::UnexpectedChar(c)),
+        }
+      }
+      rock.push(line);
+    }
+    if rock.iter().all(|l| *l == 0) {
+      return Err(ShapeError::EmptyRock(idx));
     }
+    Ok(rock)
+  }).collect::<Result<_, _>>()?;
+  if rocks.is_empty() {
+    return Err(ShapeError::NoRocks);
   }
-  line
+  Ok(rocks)
 }
 
-fn shift_left(rock: &mut Rock, skip_check: bool) -> bool {
-  if skip_check || rock.iter().all(|l| l.leading_zeros() > 1) {
+fn shift_left(rock: &mut Rock, wall: Line, skip_check: bool) -> bool {
+  if skip_check || rock.iter().all(|l| l & wall == 0) {
     rock.iter_mut().for_each(|l| *l <<= 1);
     true
   } else {
@@ -212,7 +365,7 @@ fn shift_left(rock: &mut Rock, skip_check: bool) -> bool {
 }
 
 fn shift_right(rock: &mut Rock, skip_check: bool) -> bool {
-  if skip_check || rock.iter().all(|l| l.trailing_zeros() > 0) {
+  if skip_check || rock.iter().all(|l| l & 1 == 0) {
     rock.iter_mut().for_each(|l| *l >>= 1);
     true
   } else {
@@ -220,33 +373,27 @@ fn shift_right(rock: &mut Rock, skip_check: bool) -> bool {
   }
 }
 
-fn can_move_left(rock_line: &Line, chamber_line: &Line) -> bool {
-  rock_line.leading_zeros() > 1
+fn can_move_left(rock_line: &Line, chamber_line: &Line, wall: Line) -> bool {
+  rock_line & wall == 0
     && ((rock_line << 1) & chamber_line == 0)
 }
 
 fn can_move_right(rock_line: &Line, chamber_line: &Line) -> bool {
-  rock_line.trailing_zeros() > 0
+  rock_line & 1 == 0
     && ((rock_line >> 1) & chamber_line) == 0
 }
 
-fn can_shift(rock_line: &Line, chamber_line: &Line, direction: &char) -> bool {
+fn can_shift(rock_line: &Line, chamber_line: &Line, direction: &char, wall: Line) -> bool {
   if direction == &DIRECTION_LEFT {
-    can_move_left(rock_line, chamber_line)
+    can_move_left(rock_line, chamber_line, wall)
   } else {
     can_move_right(rock_line, chamber_line)
   }
 }
 
-fn _line_to_string(line: &Line) -> String {
-  let mut s = String::new();
-  _line_into_string(line, &mut s);
-  s
-}
-
-fn _line_into_string(line: &Line, out: &mut String) {
-  for b in 0..CHAMBER_WIDTH {
-    if (line << b) & _MOST_SIG_BIT_SET == 0 {
+fn _line_into_string(line: &Line, width: u8, out: &mut String) {
+  for b in (0..width).rev() {
+    if line & (1 << b) == 0 {
       out.push('.');
     } else {
       out.push('#');
@@ -254,83 +401,86 @@ fn _line_into_string(line: &Line, out: &mut String) {
   }
 }
 
-fn _rock_to_string(lines: &Rock) -> String {
+fn _rock_to_string(lines: &Rock, width: u8) -> String {
   let mut s = String::new();
   for line in lines {
-    _line_into_string(&line, &mut s);
+    _line_into_string(line, width, &mut s);
     s.push('\n');
   }
   s
 }
 
-fn _chamber_to_string(lines: &Chamber) -> String {
+fn _chamber_to_string(lines: &Chamber, width: u8) -> String {
   let mut s = String::new();
   for line in lines.iter().rev() {
-    _line_into_string(&line, &mut s);
+    _line_into_string(line, width, &mut s);
     s.push('\n');
   }
   s
 }
 
-fn shift_rock(rock: &mut Vec<u8>, direction: &char, skip_check: bool) -> bool {
+fn shift_rock(rock: &mut Rock, direction: &char, wall: Line, skip_check: bool) -> bool {
   if direction == &DIRECTION_LEFT {
-    shift_left(rock, skip_check)
+    shift_left(rock, wall, skip_check)
   } else {
     shift_right(rock, skip_check)
   }
 }
 
+/// Where a new rock appears: its left edge `left` columns from the wall and its bottom `above`
+/// rows over the highest rock
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Spawn {
+  pub left: u8,
+  pub above: u8,
+}
+
+impl Default for Spawn {
+  fn default() -> Self {
+    Spawn { left: STARTS_LEFT, above: STARTS_ABOVE }
+  }
+}
+
 #[derive(Clone)]
 pub struct Puzzle {
   jets: Vec<char>,
   rocks: Vec<Rock>,
+  width: u8,
+  spawn: Spawn,
 }
 
 impl Puzzle {
   fn new(jets: Vec<char>) -> Puzzle {
-    if jets.iter().any(|c| c != &DIRECTION_LEFT && c != &DIRECTION_RIGHT) {
-      panic!("Unexpected jet patterns");
+    Puzzle::with_rocks(jets, PUZZLE_ROCKS, CHAMBER_WIDTH, Spawn::default())
+      .unwrap_or_else(|e| panic!("Unexpected puzzle input: {e}"))
+  }
+
+  /// A chamber of the given width, up to 64, with rocks drawn as in [PUZZLE_ROCKS]
+  pub fn with_rocks(jets: Vec<char>, rocks: &str, width: u8, spawn: Spawn) -> Result<Puzzle, ShapeError> {
+    if jets.is_empty() {
+      return Err(ShapeError::NoJets);
     }
-    let mut rocks = Vec::new();
-    let mut rock = Vec::new();
-    rock.clear();
-    rock.push(make_line("..####."));
-    rocks.push(rock.clone());
-
-    rock.clear();
-    rock.push(make_line("...#..."));
-    rock.push(make_line("..###.."));
-    rock.push(make_line("...#..."));
-    rocks.push(rock.clone());
-
-    rock.clear();
-    rock.push(make_line("....#.."));
-    rock.push(make_line("....#.."));
-    rock.push(make_line("..###.."));
-    rocks.push(rock.clone());
-
-    rock.clear();
-    rock.push(make_line("..#...."));
-    rock.push(make_line("..#...."));
-    rock.push(make_line("..#...."));
-    rock.push(make_line("..#...."));
-    rocks.push(rock.clone());
-
-    rock.clear();
-    rock.push(make_line("..##..."));
-    rock.push(make_line("..##..."));
-    rocks.push(rock.clone());
-
-    Puzzle {
-      jets,
-      rocks,
+    if let Some(c) = jets.iter().find(|c| *c != &DIRECTION_LEFT && *c != &DIRECTION_RIGHT) {
+      return Err(ShapeError::UnexpectedJet(*c));
     }
+    Ok(Puzzle {
+      jets,
+      rocks: parse_rocks(rocks, width, spawn.left)?,
+      width,
+      spawn,
+    })
+  }
+
+  /// The bit of the leftmost column
+  fn wall(&self) -> Line {
+    1 << (self.width - 1)
   }
 }
 
 #[cfg(test)]
 mod tests {
-  use crate::day17::{Chamber, _chamber_to_string, generator, part1, _rock_to_string, shift_left, shift_right, Simulation, part2};
+  use crate::day17::{Chamber, _chamber_to_string, generator, part1, _rock_to_string, shift_left, shift_right, Simulation,
+                     part2, Puzzle, PUZZLE_ROCKS, ShapeError, Spawn, Tower, HeightSeries};
 
   const INPUT: &str = ">>><<><>><<<>><>>><<<>>><<<><<<>><>><<>>";
 
@@ -351,68 +501,68 @@ mod tests {
   fn test_rocks() {
     let puzzle = generator(INPUT);
     assert_eq!("..####.\n",
-               _rock_to_string(&puzzle.rocks[0]));
+               _rock_to_string(&puzzle.rocks[0], 7));
     assert_eq!("...#...\n\
                 ..###..\n\
                 ...#...\n",
-               _rock_to_string(&puzzle.rocks[1]));
+               _rock_to_string(&puzzle.rocks[1], 7));
     assert_eq!("....#..\n\
                 ....#..\n\
                 ..###..\n",
-               _rock_to_string(&puzzle.rocks[2]));
+               _rock_to_string(&puzzle.rocks[2], 7));
     assert_eq!("..#....\n\
                 ..#....\n\
                 ..#....\n\
                 ..#....\n",
-               _rock_to_string(&puzzle.rocks[3]));
+               _rock_to_string(&puzzle.rocks[3], 7));
     assert_eq!("..##...\n\
                 ..##...\n",
-               _rock_to_string(&puzzle.rocks[4]));
+               _rock_to_string(&puzzle.rocks[4], 7));
   }
 
   #[test]
   fn test_shifts() {
     let puzzle = generator(INPUT);
     let mut rock = puzzle.rocks[0].clone();
-    assert_eq!(true, shift_left(&mut rock, false));
+    assert_eq!(true, shift_left(&mut rock, 1 << 6, false));
     assert_eq!(".####..\n",
-               _rock_to_string(&rock));
-    assert_eq!(true, shift_left(&mut rock, false));
+               _rock_to_string(&rock, 7));
+    assert_eq!(true, shift_left(&mut rock, 1 << 6, false));
     assert_eq!("####...\n",
-               _rock_to_string(&rock));
-    assert_eq!(false, shift_left(&mut rock, false));
+               _rock_to_string(&rock, 7));
+    assert_eq!(false, shift_left(&mut rock, 1 << 6, false));
     assert_eq!("####...\n",
-               _rock_to_string(&rock));
+               _rock_to_string(&rock, 7));
 
     rock = puzzle.rocks[1].clone();
-    assert_eq!(true, shift_left(&mut rock, false));
+    assert_eq!(true, shift_left(&mut rock, 1 << 6, false));
     assert_eq!("..#....\n\
                 .###...\n\
                 ..#....\n",
-               _rock_to_string(&rock));
-    assert_eq!(true, shift_left(&mut rock, false));
+               _rock_to_string(&rock, 7));
+    assert_eq!(true, shift_left(&mut rock, 1 << 6, false));
     assert_eq!(".#.....\n\
                 ###....\n\
                 .#.....\n",
-               _rock_to_string(&rock));
-    assert_eq!(false, shift_left(&mut rock, false));
+               _rock_to_string(&rock, 7));
+    assert_eq!(false, shift_left(&mut rock, 1 << 6, false));
     assert_eq!(".#.....\n\
                 ###....\n\
                 .#.....\n",
-               _rock_to_string(&rock));
+               _rock_to_string(&rock, 7));
     assert_eq!(true, shift_right(&mut rock, false));
     assert_eq!(true, shift_right(&mut rock, false));
     assert_eq!("...#...\n\
                 ..###..\n\
                 ...#...\n",
-               _rock_to_string(&rock));
+               _rock_to_string(&rock, 7));
     assert_eq!(true, shift_right(&mut rock, false));
     assert_eq!(true, shift_right(&mut rock, false));
     assert_eq!(false, shift_right(&mut rock, false));
     assert_eq!(".....#.\n\
                 ....###\n\
                 .....#.\n",
-               _rock_to_string(&rock));
+               _rock_to_string(&rock, 7));
   }
 
   #[test]
@@ -423,7 +573,7 @@ mod tests {
     let rock = simulation.next_rock();
     simulation.simulate_rock(rock, &mut chamber);
     assert_eq!(1, chamber.len());
-    assert_eq!("..####.\n", _chamber_to_string(&chamber));
+    assert_eq!("..####.\n", _chamber_to_string(&chamber, 7));
     let rock = simulation.next_rock();
     simulation.simulate_rock(rock, &mut chamber);
     assert_eq!(4, chamber.len());
@@ -431,7 +581,7 @@ mod tests {
                 ..###..\n\
                 ...#...\n\
                 ..####.\n",
-               _chamber_to_string(&chamber));
+               _chamber_to_string(&chamber, 7));
     let rock = simulation.next_rock();
     simulation.simulate_rock(rock, &mut chamber);
     assert_eq!("..#....\n\
@@ -440,7 +590,7 @@ mod tests {
                 ..###..\n\
                 ...#...\n\
                 ..####.\n",
-               _chamber_to_string(&chamber));
+               _chamber_to_string(&chamber, 7));
     let rock = simulation.next_rock();
     simulation.simulate_rock(rock, &mut chamber);
     assert_eq!("....#..\n\
@@ -450,7 +600,7 @@ mod tests {
                 ..###..\n\
                 ...#...\n\
                 ..####.\n",
-               _chamber_to_string(&chamber));
+               _chamber_to_string(&chamber, 7));
     let rock = simulation.next_rock();
     simulation.simulate_rock(rock, &mut chamber);
     assert_eq!("....##.\n\
@@ -462,7 +612,7 @@ mod tests {
                 ..###..\n\
                 ...#...\n\
                 ..####.\n",
-               _chamber_to_string(&chamber));
+               _chamber_to_string(&chamber, 7));
     let rock = simulation.next_rock();
     simulation.simulate_rock(rock, &mut chamber);
     assert_eq!(".####..\n\
@@ -475,7 +625,7 @@ mod tests {
                 ..###..\n\
                 ...#...\n\
                 ..####.\n",
-               _chamber_to_string(&chamber));
+               _chamber_to_string(&chamber, 7));
   }
 
   #[test]
@@ -500,4 +650,67 @@ mod tests {
     let puzzle = generator(INPUT);
     assert_eq!(1514285714288, part2(&puzzle));
   }
-}
\ No newline at end of file
+
+  #[test]
+  fn test_custom_rocks() {
+    let jets: Vec<char> = INPUT.chars().collect();
+    let default = Puzzle::with_rocks(jets.clone(), PUZZLE_ROCKS, 7, Spawn::default()).unwrap();
+    assert_eq!(3068, Simulation::new(default).run(2022));
+
+    // A single-cell rock in a single column just stacks up
+    let column = Puzzle::with_rocks(jets.clone(), "#", 1, Spawn { left: 0, above: 0 }).unwrap();
+    assert_eq!(Tower { height: 10, profile: vec![Some(0)] }, Simulation::new(column).tower(10));
+
+    let wide = Puzzle::with_rocks(jets.clone(), PUZZLE_ROCKS, 64, Spawn { left: 30, above: 5 }).unwrap();
+    let mut simulation = Simulation::new(wide);
+    let tower = simulation.tower(500);
+    assert_eq!(64, tower.profile.len());
+    assert_eq!(tower.height as u128, simulation.run(500));
+    assert!(tower.profile.iter().flatten().any(|d| *d == 0));
+
+    assert_eq!(Some(ShapeError::TooWide(0)),
+               Puzzle::with_rocks(jets.clone(), PUZZLE_ROCKS, 7, Spawn { left: 4, above: 3 }).err());
+    assert_eq!(Some(ShapeError::ChamberWidth(65)),
+               Puzzle::with_rocks(jets.clone(), PUZZLE_ROCKS, 65, Spawn::default()).err());
+    assert_eq!(Some(ShapeError::UnexpectedChar('x')),
+               Puzzle::with_rocks(jets.clone(), "#x", 7, Spawn::default()).err());
+    assert_eq!(Some(ShapeError::EmptyRock(1)),
+               Puzzle::with_rocks(jets, "#\n\n..", 7, Spawn::default()).err());
+    assert_eq!(Some(ShapeError::NoJets),
+               Puzzle::with_rocks(Vec::new(), PUZZLE_ROCKS, 7, Spawn::default()).err());
+    assert_eq!(Some(ShapeError::UnexpectedJet('^')),
+               Puzzle::with_rocks(vec!['<', '^', '>'], PUZZLE_ROCKS, 7, Spawn::default()).err());
+  }
+
+  #[test]
+  fn test_tower() {
+    let puzzle = generator(INPUT);
+    let mut simulation = Simulation::new(puzzle);
+    let tower = simulation.tower(10);
+    assert_eq!(17, tower.height);
+    // The top of the tower in the puzzle after ten rocks
+    assert_eq!(vec![Some(3), Some(3), Some(4), Some(4), Some(0), Some(2), None], tower.profile);
+    assert_eq!(3068, simulation.tower(2022).height);
+  }
+
+  #[test]
+  fn test_height_series() {
+    let puzzle = generator(INPUT);
+    let series = HeightSeries::new(&puzzle);
+    assert_eq!(35, series.cycle());
+    assert_eq!(53, series.gain());
+    let mut simulation = Simulation::new(puzzle);
+    for n in [0, 1, 9, series.prefix(), series.prefix() + series.cycle(), 500, 2022] {
+      assert_eq!(simulation.tower(n).height as u128, series.height(n), "after {n} rocks");
+    }
+    assert_eq!(1514285714288, series.height(1000000000000));
+    assert!(series.height(u64::MAX) > u64::MAX as u128);
+
+    let path = std::env::temp_dir().join(format!("day17-series-{}.json", std::process::id()));
+    series.save(&path).unwrap();
+    let loaded = HeightSeries::load(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(series, loaded);
+    assert!(HeightSeries::load(&path).is_err());
+  }
+}
@@ -1,6 +1,6 @@
-use std::collections::{HashMap, HashSet};
+use std::collections::{HashMap, HashSet, VecDeque};
 
-pub fn generator(input: &str) -> HashSet<Position> {
+pub fn generator(input: &str) -> VoxelSet {
   input.lines()
        .map(|l| {
          let mut splits = l.split(',');
@@ -12,184 +12,461 @@ pub fn generator(input: &str) -> HashSet<Position> {
        }).collect()
 }
 
-#[derive(Eq, PartialEq, Hash, Debug, Clone)]
+#[derive(Eq, PartialEq, Hash, Debug, Clone, Copy, Ord, PartialOrd)]
 pub struct Position {
-  x: u32,
-  y: u32,
-  z: u32,
+  pub x: i32,
+  pub y: i32,
+  pub z: i32,
 }
 
+/// The directions of the six faces of a cube
+const FACES: [(i32, i32, i32); 6] = [(1, 0, 0), (-1, 0, 0), (0, 1, 0), (0, -1, 0), (0, 0, 1), (0, 0, -1)];
+
 impl Position {
-  fn new(x: u32, y: u32, z: u32) -> Position {
-    if x == 0 || y == 0 || z == 0 {
-      panic!("Expecting coordinates > 1, but received ({x},{y},{z})");
-    }
+  pub fn new(x: i32, y: i32, z: i32) -> Position {
     Position { x, y, z }
   }
 
-  fn change_x(&self, x: isize) -> Position {
-    if x != -1 && x != 1 {
-      panic!("Unexpected value, expecting either -1 or 1");
-    }
-    let mut result = self.clone();
-    result.x = (result.x as isize + x) as u32;
-    result
+  fn step(&self, (dx, dy, dz): (i32, i32, i32)) -> Position {
+    Position { x: self.x + dx, y: self.y + dy, z: self.z + dz }
   }
 
-  fn change_y(&self, y: isize) -> Position {
-    if y != -1 && y != 1 {
-      panic!("Unexpected value, expecting either -1 or 1");
-    }
-    let mut result = self.clone();
-    result.y = (result.y as isize + y) as u32;
-    result
+  fn neighbors(&self) -> impl Iterator<Item=Position> + '_ {
+    FACES.iter().map(|f| self.step(*f))
   }
+}
 
-  fn change_z(&self, z: isize) -> Position {
-    if z != -1 && z != 1 {
-      panic!("Unexpected value, expecting either -1 or 1");
-    }
-    let mut result = self.clone();
-    result.z = (result.z as isize + z) as u32;
-    result
-  }
+pub fn part1(droplet: &VoxelSet) -> u32 {
+  droplet.surface_area()
 }
 
-pub fn part1(positions: &HashSet<Position>) -> u32 {
-  let mut count: u32 = 0;
-  for position in positions {
-    // Check the x neighbors
-    for c in [-1, 1] {
-      let p = position.change_x(c);
-      if !positions.contains(&p) {
-        count += 1;
-      }
-    }
+pub fn part2(droplet: &VoxelSet) -> u32 {
+  droplet.exterior_area()
+}
 
-    // Check the y neighbors
-    for c in [-1, 1] {
-      let p = position.change_y(c);
-      if !positions.contains(&p) {
-        count += 1;
-      }
-    }
+/// Air trapped inside the droplet
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct AirPocket {
+  pub cells: Vec<Position>,
+  /// The faces of the droplet bordering the pocket
+  pub surface: u32,
+}
 
-    // Check the z neighbors
-    for c in [-1, 1] {
-      let p = position.change_z(c);
-      if !positions.contains(&p) {
-        count += 1;
-      }
-    }
+impl AirPocket {
+  pub fn volume(&self) -> usize {
+    self.cells.len()
   }
+}
 
-  count
+/// Which faces of the droplet to include in a mesh
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Surface {
+  /// Every face not touching another cube, including those around air pockets
+  All,
+  /// Only the faces that can be reached from outside
+  Exterior,
 }
 
-pub fn part2(positions: &HashSet<Position>) -> u32 {
-  // Find spaces that are not open and exclude them from the counts
-  let mut min_pos = Position::new(u32::MAX, u32::MAX, u32::MAX);
-  let mut max_pos = Position::new(1, 1, 1);
-  for position in positions {
-    min_pos.x = min_pos.x.min(position.x);
-    min_pos.y = min_pos.y.min(position.y);
-    min_pos.z = min_pos.z.min(position.z);
+/// A set of unit cubes
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct VoxelSet {
+  cubes: HashSet<Position>,
+}
 
-    max_pos.x = max_pos.x.max(position.x);
-    max_pos.y = max_pos.y.max(position.y);
-    max_pos.z = max_pos.z.max(position.z);
+impl FromIterator<Position> for VoxelSet {
+  fn from_iter<T: IntoIterator<Item=Position>>(iter: T) -> Self {
+    VoxelSet { cubes: iter.into_iter().collect() }
   }
+}
 
-  let mut count: u32 = 0;
-  let mut empty_status = HashMap::new();
-  for position in positions {
-    // Check the x neighbors
-    for c in [-1, 1] {
-      let p = position.change_x(c);
-      if !positions.contains(&p) && is_space_open(p, positions, &min_pos, &max_pos, &mut empty_status) {
-        count += 1;
+impl VoxelSet {
+  pub fn len(&self) -> usize {
+    self.cubes.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.cubes.is_empty()
+  }
+
+  pub fn contains(&self, position: &Position) -> bool {
+    self.cubes.contains(position)
+  }
+
+  /// The corners of the smallest box holding every cube
+  pub fn bounds(&self) -> Option<(Position, Position)> {
+    let first = *self.cubes.iter().next()?;
+    Some(self.cubes.iter().fold((first, first), |(min, max), p| {
+      (Position::new(min.x.min(p.x), min.y.min(p.y), min.z.min(p.z)),
+       Position::new(max.x.max(p.x), max.y.max(p.y), max.z.max(p.z)))
+    }))
+  }
+
+  /// Faces not touching another cube
+  pub fn surface_area(&self) -> u32 {
+    self.cubes.iter()
+      .map(|c| c.neighbors().filter(|n| !self.cubes.contains(n)).count() as u32)
+      .sum()
+  }
+
+  /// The air around the droplet, flooded from outside a box one bigger than its bounds
+  fn exterior_air(&self) -> HashSet<Position> {
+    let mut outside = HashSet::new();
+    let Some((min, max)) = self.bounds() else { return outside };
+    let (min, max) = (min.step((-1, -1, -1)), max.step((1, 1, 1)));
+    let in_box = |p: &Position| (min.x..=max.x).contains(&p.x)
+      && (min.y..=max.y).contains(&p.y)
+      && (min.z..=max.z).contains(&p.z);
+    let mut queue = VecDeque::from([min]);
+    outside.insert(min);
+    while let Some(curr) = queue.pop_front() {
+      for n in curr.neighbors() {
+        if in_box(&n) && !self.cubes.contains(&n) && outside.insert(n) {
+          queue.push_back(n);
+        }
       }
     }
+    outside
+  }
+
+  /// Faces that can be reached from outside the droplet
+  pub fn exterior_area(&self) -> u32 {
+    let outside = self.exterior_air();
+    self.cubes.iter()
+      .map(|c| c.neighbors().filter(|n| outside.contains(n)).count() as u32)
+      .sum()
+  }
 
-    // Check the y neighbors
-    for c in [-1, 1] {
-      let p = position.change_y(c);
-      if !positions.contains(&p) && is_space_open(p, positions, &min_pos, &max_pos, &mut empty_status) {
-        count += 1;
+  /// Faces bordering air pockets
+  pub fn interior_area(&self) -> u32 {
+    self.surface_area() - self.exterior_area()
+  }
+
+  /// Each separate pocket of trapped air, largest first
+  pub fn air_pockets(&self) -> Vec<AirPocket> {
+    let outside = self.exterior_air();
+    let Some((min, max)) = self.bounds() else { return Vec::new() };
+    let mut seen: HashSet<Position> = HashSet::new();
+    let mut pockets = Vec::new();
+    for x in min.x..=max.x {
+      for y in min.y..=max.y {
+        for z in min.z..=max.z {
+          let start = Position::new(x, y, z);
+          if self.cubes.contains(&start) || outside.contains(&start) || seen.contains(&start) {
+            continue;
+          }
+          // Anything not reached from outside is enclosed, so the flood stays inside the bounds
+          let cells = flood(start, &mut seen, |p| !self.cubes.contains(p));
+          let surface = cells.iter()
+            .map(|c| c.neighbors().filter(|n| self.cubes.contains(n)).count() as u32)
+            .sum();
+          pockets.push(AirPocket { cells, surface });
+        }
       }
     }
+    pockets.sort_by_key(|p| std::cmp::Reverse(p.volume()));
+    pockets
+  }
 
-    // Check the z neighbors
-    for c in [-1, 1] {
-      let p = position.change_z(c);
-      if !positions.contains(&p) && is_space_open(p, positions, &min_pos, &max_pos, &mut empty_status) {
-        count += 1;
+  /// The pieces of the droplet whose cubes share faces, largest first
+  pub fn components(&self) -> Vec<VoxelSet> {
+    let mut seen: HashSet<Position> = HashSet::new();
+    let mut starts: Vec<&Position> = self.cubes.iter().collect();
+    starts.sort();
+    let mut components: Vec<VoxelSet> = starts.into_iter()
+      .filter_map(|start| {
+        if seen.contains(start) {
+          return None;
+        }
+        Some(flood(*start, &mut seen, |p| self.cubes.contains(p)).into_iter().collect())
+      })
+      .collect();
+    components.sort_by_key(|c| std::cmp::Reverse(c.len()));
+    components
+  }
+
+  /// The faces to draw, each as a cube and the direction the face points
+  fn faces(&self, surface: Surface) -> Vec<(Position, (i32, i32, i32))> {
+    let outside = match surface {
+      Surface::All => None,
+      Surface::Exterior => Some(self.exterior_air()),
+    };
+    let mut cubes: Vec<&Position> = self.cubes.iter().collect();
+    cubes.sort();
+    cubes.into_iter().flat_map(|c| FACES.iter().map(move |f| (*c, *f)))
+      .filter(|(c, f)| {
+        let n = c.step(*f);
+        !self.cubes.contains(&n) && outside.as_ref().is_none_or(|o| o.contains(&n))
+      })
+      .collect()
+  }
+
+  /// The droplet as an ASCII STL mesh with two triangles for each face
+  pub fn to_stl(&self, surface: Surface) -> String {
+    let mut out = String::from("solid droplet\n");
+    for (cube, normal) in self.faces(surface) {
+      let [a, b, c, d] = face_corners(&cube, normal);
+      for triangle in [[a, b, c], [a, c, d]] {
+        out.push_str(&format!("  facet normal {} {} {}\n    outer loop\n", normal.0, normal.1, normal.2));
+        for v in triangle {
+          out.push_str(&format!("      vertex {} {} {}\n", v.x, v.y, v.z));
+        }
+        out.push_str("    endloop\n  endfacet\n");
       }
     }
+    out.push_str("endsolid droplet\n");
+    out
+  }
+
+  /// The droplet as a Wavefront OBJ mesh with a quad for each face, sharing vertices
+  pub fn to_obj(&self, surface: Surface) -> String {
+    let mut vertices: Vec<Position> = Vec::new();
+    let mut index: HashMap<Position, usize> = HashMap::new();
+    let mut faces = Vec::new();
+    for (cube, normal) in self.faces(surface) {
+      let ids: Vec<usize> = face_corners(&cube, normal).iter().map(|v| {
+        *index.entry(*v).or_insert_with(|| {
+          vertices.push(*v);
+          vertices.len()
+        })
+      }).collect();
+      faces.push(ids);
+    }
+    let mut out = String::new();
+    for v in vertices {
+      out.push_str(&format!("v {} {} {}\n", v.x, v.y, v.z));
+    }
+    for f in faces {
+      out.push_str(&format!("f {} {} {} {}\n", f[0], f[1], f[2], f[3]));
+    }
+    out
   }
+}
 
-  count
+/// The measurements that do not depend on how the cubes are stored
+pub trait Droplet {
+  /// Faces not touching another cube
+  fn surface_area(&self) -> u32;
+  /// Faces that can be reached from outside the droplet
+  fn exterior_area(&self) -> u32;
 }
 
-fn is_space_open(position: Position, positions: &HashSet<Position>,
-                 min_pos: &Position, max_pos: &Position,
-                 empty_status: &mut HashMap<Position, bool>) -> bool {
-  let mut visited: HashSet<Position> = HashSet::new();
-  let mut stack: Vec<Position> = Vec::new();
-  stack.push(position);
-  let mut open: bool = false;
-  while !stack.is_empty() {
-    // Navigate on empty neighbors until you get to out of bounds or exhaust the stack
-    let curr = stack.pop().unwrap();
-    if visited.contains(&curr) {
-      continue;
+impl Droplet for VoxelSet {
+  fn surface_area(&self) -> u32 {
+    VoxelSet::surface_area(self)
+  }
+
+  fn exterior_area(&self) -> u32 {
+    VoxelSet::exterior_area(self)
+  }
+}
+
+/// Cubes stored as a 3D bitset over their bounds with a layer of air all round, each row along the
+/// z axis packed into words. Suits large, dense droplets better than hashing every position.
+#[derive(Clone, Debug)]
+pub struct DenseVoxels {
+  /// The position of the first cell
+  origin: Position,
+  nx: usize,
+  ny: usize,
+  nz: usize,
+  /// Words in each row
+  words: usize,
+  cubes: Vec<u64>,
+}
+
+impl DenseVoxels {
+  pub fn new(voxels: &VoxelSet) -> DenseVoxels {
+    let (min, max) = voxels.bounds().unwrap_or((Position::new(0, 0, 0), Position::new(0, 0, 0)));
+    let origin = min.step((-1, -1, -1));
+    let (nx, ny, nz) = ((max.x - min.x + 3) as usize, (max.y - min.y + 3) as usize, (max.z - min.z + 3) as usize);
+    let words = nz.div_ceil(64);
+    let mut dense = DenseVoxels { origin, nx, ny, nz, words, cubes: vec![0; nx * ny * words] };
+    for p in &voxels.cubes {
+      let (x, y, z) = ((p.x - origin.x) as usize, (p.y - origin.y) as usize, (p.z - origin.z) as usize);
+      let idx = dense.row(x, y) + z / 64;
+      dense.cubes[idx] |= 1 << (z % 64);
     }
-    if empty_status.contains_key(&curr) {
-      open = *empty_status.get(&curr).unwrap();
-      break;
+    dense
+  }
+
+  fn row(&self, x: usize, y: usize) -> usize {
+    (x * self.ny + y) * self.words
+  }
+
+  pub fn len(&self) -> usize {
+    self.cubes.iter().map(|w| w.count_ones() as usize).sum()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.cubes.iter().all(|w| *w == 0)
+  }
+
+  pub fn contains(&self, p: &Position) -> bool {
+    let (x, y, z) = (p.x - self.origin.x, p.y - self.origin.y, p.z - self.origin.z);
+    if x < 0 || y < 0 || z < 0 || x as usize >= self.nx || y as usize >= self.ny || z as usize >= self.nz {
+      return false;
     }
-    if curr.x <= min_pos.x || curr.x >= max_pos.x
-      || curr.y <= min_pos.y || curr.y >= max_pos.y
-      || curr.z <= min_pos.z || curr.z >= max_pos.z {
-      // If we have come to the edge then mark this as open
-      open = true;
-      break;
+    let (x, y, z) = (x as usize, y as usize, z as usize);
+    self.cubes[self.row(x, y) + z / 64] & (1 << (z % 64)) != 0
+  }
+
+  /// The rows next to the given one in x and y that are inside the grid
+  fn neighbor_rows(&self, x: usize, y: usize) -> impl Iterator<Item=(usize, usize)> + '_ {
+    [(x.wrapping_sub(1), y), (x + 1, y), (x, y.wrapping_sub(1)), (x, y + 1)].into_iter()
+      .filter(|&(x, y)| x < self.nx && y < self.ny)
+  }
+
+  /// The bits of the last word of a row that are inside the grid
+  fn last_mask(&self) -> u64 {
+    match self.nz % 64 {
+      0 => u64::MAX,
+      bits => (1 << bits) - 1,
     }
-    // Check the x neighbors
-    for c in [-1, 1] {
-      match curr.change_x(c) {
-        p if !positions.contains(&p) => stack.push(p),
-        _ => {}
+  }
+
+  /// Fill the outside along the row starting at the given word as far as the air allows
+  fn fill_row(&self, outside: &mut [u64], start: usize) {
+    let free: Vec<u64> = (0..self.words).map(|w| {
+      let mask = if w + 1 == self.words { self.last_mask() } else { u64::MAX };
+      !self.cubes[start + w] & mask
+    }).collect();
+    let row = &mut outside[start..start + self.words];
+    loop {
+      let before: Vec<u64> = row.to_vec();
+      for w in 0..self.words {
+        row[w] = spread(row[w], free[w]);
+        if w + 1 < self.words && row[w] >> 63 == 1 {
+          row[w + 1] |= free[w + 1] & 1;
+        }
+      }
+      for w in (1..self.words).rev() {
+        row[w] = spread(row[w], free[w]);
+        if row[w] & 1 == 1 {
+          row[w - 1] |= free[w - 1] & (1 << 63);
+        }
+      }
+      if row == before.as_slice() {
+        break;
       }
     }
+  }
 
-    // Check the y neighbors
-    for c in [-1, 1] {
-      match curr.change_y(c) {
-        p if !positions.contains(&p) => stack.push(p),
-        _ => {}
+  /// The air reachable from the corner, filling whole rows at a time
+  fn exterior_air(&self) -> Vec<u64> {
+    let mut outside = vec![0; self.cubes.len()];
+    outside[0] = 1;
+    let mut queue = vec![(0, 0)];
+    while let Some((x, y)) = queue.pop() {
+      let start = self.row(x, y);
+      self.fill_row(&mut outside, start);
+      for (nx, ny) in self.neighbor_rows(x, y) {
+        let n = self.row(nx, ny);
+        let mut changed = false;
+        for w in 0..self.words {
+          let seeds = outside[start + w] & !self.cubes[n + w] & !outside[n + w];
+          if seeds != 0 {
+            outside[n + w] |= seeds;
+            changed = true;
+          }
+        }
+        if changed {
+          queue.push((nx, ny));
+        }
       }
     }
+    outside
+  }
 
-    // Check the z neighbors
-    for c in [-1, 1] {
-      match curr.change_z(c) {
-        p if !positions.contains(&p) => stack.push(p),
-        _ => {}
+  /// Count the faces of cubes that touch a cell of the given set
+  fn faces_touching(&self, cells: &[u64]) -> u32 {
+    let mut count = 0;
+    for x in 0..self.nx {
+      for y in 0..self.ny {
+        let start = self.row(x, y);
+        for w in 0..self.words {
+          let cubes = self.cubes[start + w];
+          if cubes == 0 {
+            continue;
+          }
+          let below = (cells[start + w] << 1) | if w > 0 { cells[start + w - 1] >> 63 } else { 0 };
+          let above = (cells[start + w] >> 1) | if w + 1 < self.words { cells[start + w + 1] << 63 } else { 0 };
+          count += (cubes & below).count_ones() + (cubes & above).count_ones();
+          for (nx, ny) in self.neighbor_rows(x, y) {
+            count += (cubes & cells[self.row(nx, ny) + w]).count_ones();
+          }
+        }
       }
     }
-    visited.insert(curr);
+    count
   }
-  // Mark the visited positions
-  for position in visited {
-    empty_status.insert(position, open);
+}
+
+impl Droplet for DenseVoxels {
+  fn surface_area(&self) -> u32 {
+    let air: Vec<u64> = self.cubes.iter().enumerate().map(|(i, c)| {
+      let mask = if (i + 1) % self.words == 0 { self.last_mask() } else { u64::MAX };
+      !c & mask
+    }).collect();
+    self.faces_touching(&air)
+  }
+
+  fn exterior_area(&self) -> u32 {
+    self.faces_touching(&self.exterior_air())
+  }
+}
+
+/// Grow the set bits of a word into the neighbouring free bits in both directions
+fn spread(seed: u64, free: u64) -> u64 {
+  let mut fill = seed & free;
+  // Each round doubles how far the fill reaches through runs of free bits
+  let (mut up, mut down) = (free, free);
+  for shift in [1, 2, 4, 8, 16, 32] {
+    fill |= up & (fill << shift);
+    up &= up << shift;
+    fill |= down & (fill >> shift);
+    down &= down >> shift;
+  }
+  fill
+}
+
+/// The corners of a face of the cube, counter-clockwise when seen from outside
+fn face_corners(cube: &Position, normal: (i32, i32, i32)) -> [Position; 4] {
+  // Two edges whose cross product points along the normal
+  let (u, v) = match normal {
+    (1, 0, 0) => ((0, 1, 0), (0, 0, 1)),
+    (-1, 0, 0) => ((0, 0, 1), (0, 1, 0)),
+    (0, 1, 0) => ((0, 0, 1), (1, 0, 0)),
+    (0, -1, 0) => ((1, 0, 0), (0, 0, 1)),
+    (0, 0, 1) => ((1, 0, 0), (0, 1, 0)),
+    _ => ((0, 1, 0), (1, 0, 0)),
+  };
+  let base = cube.step((normal.0.max(0), normal.1.max(0), normal.2.max(0)));
+  [base, base.step(u), base.step(u).step(v), base.step(v)]
+}
+
+/// Every position connected to the start through positions that pass the test, marking them seen
+fn flood(start: Position, seen: &mut HashSet<Position>, passable: impl Fn(&Position) -> bool) -> Vec<Position> {
+  let mut cells = vec![start];
+  seen.insert(start);
+  let mut idx = 0;
+  while idx < cells.len() {
+    let curr = cells[idx];
+    idx += 1;
+    for n in curr.neighbors() {
+      if passable(&n) && seen.insert(n) {
+        cells.push(n);
+      }
+    }
   }
-  open
+  cells.sort();
+  cells
 }
 
 #[cfg(test)]
 mod tests {
-  use crate::day18::{generator, part1, part2};
+  use rand::{Rng, SeedableRng};
+  use rand::rngs::StdRng;
+  use crate::day18::{DenseVoxels, Droplet, generator, part1, part2, Position, spread, Surface, VoxelSet};
 
   fn input() -> String {
     vec![
@@ -226,4 +503,85 @@ mod tests {
     let droplets = generator(input().as_str());
     assert_eq!(58, part2(&droplets));
   }
-}
\ No newline at end of file
+
+  #[test]
+  fn test_air_pockets() {
+    let droplet = generator(input().as_str());
+    let pockets = droplet.air_pockets();
+    assert_eq!(1, pockets.len());
+    assert_eq!(vec![Position::new(2, 2, 5)], pockets[0].cells);
+    assert_eq!(6, pockets[0].surface);
+    assert_eq!(6, droplet.interior_area());
+
+    // A hollow 5x5x5 shell holds a single pocket of 27 cells
+    let hollow: VoxelSet = (0..125).map(|i| Position::new(i / 25, i / 5 % 5, i % 5))
+      .filter(|p| [p.x, p.y, p.z].iter().any(|c| *c == 0 || *c == 4))
+      .collect();
+    let pockets = hollow.air_pockets();
+    assert_eq!(vec![27], pockets.iter().map(|p| p.volume()).collect::<Vec<usize>>());
+    assert_eq!(54, pockets[0].surface);
+    assert_eq!(hollow.surface_area(), hollow.exterior_area() + hollow.interior_area());
+    assert_eq!(150, hollow.exterior_area());
+  }
+
+  #[test]
+  fn test_components() {
+    let droplet = generator(input().as_str());
+    let components = droplet.components();
+    assert_eq!(vec![8, 1, 1, 1, 1, 1], components.iter().map(|c| c.len()).collect::<Vec<usize>>());
+    assert!(components[0].contains(&Position::new(2, 2, 4)));
+    assert_eq!(droplet.surface_area(), components.iter().map(|c| c.surface_area()).sum::<u32>());
+  }
+
+  #[test]
+  fn test_meshes() {
+    let cube: VoxelSet = [Position::new(0, 0, 0)].into_iter().collect();
+    let stl = cube.to_stl(Surface::All);
+    assert_eq!(12, stl.matches("facet normal").count());
+    assert!(stl.starts_with("solid droplet\n  facet normal 1 0 0\n    outer loop\n      vertex 1 0 0\n"));
+    let obj = cube.to_obj(Surface::All);
+    assert_eq!(8, obj.lines().filter(|l| l.starts_with("v ")).count());
+    assert_eq!(6, obj.lines().filter(|l| l.starts_with("f ")).count());
+
+    let droplet = generator(input().as_str());
+    assert_eq!(2 * 64, droplet.to_stl(Surface::All).matches("facet normal").count());
+    assert_eq!(2 * 58, droplet.to_stl(Surface::Exterior).matches("facet normal").count());
+    assert_eq!(58, droplet.to_obj(Surface::Exterior).lines().filter(|l| l.starts_with("f ")).count());
+  }
+
+  #[test]
+  fn test_spread() {
+    assert_eq!(0b0011_1100, spread(0b0000_1000, 0b1011_1101));
+    assert_eq!(0, spread(0b10, 0b01));
+    assert_eq!(u64::MAX, spread(1 << 40, u64::MAX));
+  }
+
+  #[test]
+  fn test_dense() {
+    let droplet = generator(input().as_str());
+    let dense = DenseVoxels::new(&droplet);
+    assert_eq!(13, dense.len());
+    assert!(dense.contains(&Position::new(2, 2, 6)));
+    assert!(!dense.contains(&Position::new(2, 2, 5)));
+    assert!(!dense.contains(&Position::new(-20, 2, 5)));
+    assert_eq!(64, Droplet::surface_area(&dense));
+    assert_eq!(58, Droplet::exterior_area(&dense));
+  }
+
+  #[test]
+  fn test_backends_agree() {
+    let mut rng = StdRng::seed_from_u64(18);
+    for round in 0..20 {
+      // Long in z so rows span several words
+      let (nx, ny, nz) = (rng.gen_range(1..12), rng.gen_range(1..12), if round % 2 == 0 { 200 } else { 30 });
+      let voxels: VoxelSet = (0..nx * ny * nz)
+        .map(|i| Position::new(i / (ny * nz), i / nz % ny, i % nz))
+        .filter(|_| rng.gen_bool(0.6))
+        .collect();
+      let dense = DenseVoxels::new(&voxels);
+      assert_eq!(voxels.len(), dense.len());
+      assert_eq!(voxels.surface_area(), Droplet::surface_area(&dense));
+      assert_eq!(voxels.exterior_area(), Droplet::exterior_area(&dense));
+    }
+  }
+}
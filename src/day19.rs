@@ -1,20 +1,31 @@
-use std::collections::VecDeque;
-use crate::day19::Robot::{ClayBot, GeodeBot, ObsidianBot, OreBot};
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use crate::utils::add_stat;
+
+/// A resource collected by robots, identified by its position in the blueprint's resources
+pub type Resource = usize;
+
+/// The resources of the puzzle blueprints, in the order their robots are listed
+pub const ORE: Resource = 0;
+pub const CLAY: Resource = 1;
+pub const OBSIDIAN: Resource = 2;
+pub const GEODE: Resource = 3;
+
+/// Resources are tracked in fixed size arrays while searching to avoid allocating per state
+const MAX_RESOURCES: usize = 8;
 
 #[derive(Eq, PartialEq, Debug)]
-enum Robot {
-  OreBot,
-  ClayBot,
-  ObsidianBot,
-  GeodeBot,
+pub struct BotCost {
+  robot: Resource,
+  costs: Vec<u32>,
 }
 
-#[derive(Eq, PartialEq, Debug)]
-struct BotCost {
-  robot: Robot,
-  ore: u32,
-  clay: u32,
-  obsidian: u32,
+impl BotCost {
+  /// A robot collecting the given resource, with its cost in each of the blueprint's resources
+  pub fn new(robot: Resource, costs: Vec<u32>) -> BotCost {
+    BotCost { robot, costs }
+  }
 }
 
 pub fn generator(input: &str) -> Vec<Blueprint> {
@@ -23,183 +34,364 @@ pub fn generator(input: &str) -> Vec<Blueprint> {
 }
 
 pub fn part1(blue_prints: &Vec<Blueprint>) -> u32 {
-  blue_prints.iter().fold(0, |a, bp| {
-    a + bp.id * bp.simulate(24)
-  })
+  quality_level_sum(blue_prints, 24)
 }
 
 pub fn part2(blue_prints: &Vec<Blueprint>) -> u32 {
-  blue_prints[0..3.min(blue_prints.len())].iter().map(|bp| bp.simulate(32)).product()
+  first_n_product(blue_prints, 3, 32)
+}
+
+/// The sum of the quality levels of all the blueprints within the given time
+pub fn quality_level_sum(blue_prints: &[Blueprint], until_time: u32) -> u32 {
+  blue_prints.iter().map(|bp| bp.quality_level(until_time)).sum()
 }
 
+/// The product of the most geodes that the first `n` blueprints can open within the given time
+pub fn first_n_product(blue_prints: &[Blueprint], n: usize, until_time: u32) -> u32 {
+  blue_prints[0..n.min(blue_prints.len())].iter().map(|bp| bp.simulate(until_time)).product()
+}
+
+/// The robots that can be built and what they cost. The last resource is the one to maximise
+/// and the factory starts with a single robot collecting the first resource.
 #[derive(Eq, PartialEq, Debug)]
 pub struct Blueprint {
   id: u32,
+  resources: Vec<String>,
   bot_costs: Vec<BotCost>,
-  max_ore_cost: u32,
-  max_clay_cost: u32,
-  max_obsidian_cost: u32,
+  max_costs: Vec<u32>,
+  target: Resource,
 }
 
 impl Blueprint {
-  fn new(id: u32, bot_costs: Vec<BotCost>) -> Blueprint {
-    let max_ore_cost = bot_costs.iter().map(|bc| bc.ore).max().unwrap();
-    let max_clay_cost = bot_costs.iter().map(|bc| bc.clay).max().unwrap();
-    let max_obsidian_cost = bot_costs.iter().map(|bc| bc.obsidian).max().unwrap();
+  /// Build a blueprint from its robot costs, which are tried in the given order while searching.
+  /// There must be between one and eight resources, and every robot must collect one of them and
+  /// give a cost in each of them, and this panics otherwise.
+  pub fn new(id: u32, resources: Vec<String>, bot_costs: Vec<BotCost>) -> Blueprint {
+    assert!(!resources.is_empty(), "Expecting at least one resource");
+    assert!(resources.len() <= MAX_RESOURCES, "Expecting at most {MAX_RESOURCES} resources, got {}", resources.len());
+    for bc in &bot_costs {
+      assert!(bc.robot < resources.len(), "Expecting a robot for one of the {} resources, got {}",
+              resources.len(), bc.robot);
+      assert_eq!(resources.len(), bc.costs.len(), "Expecting a cost for each resource for robot {}", bc.robot);
+    }
+    let max_costs = (0..resources.len())
+      .map(|r| bot_costs.iter().map(|bc| bc.costs[r]).max().unwrap_or(0))
+      .collect();
+    let target = resources.len() - 1;
     Blueprint {
       id,
+      resources,
       bot_costs,
-      max_ore_cost,
-      max_clay_cost,
-      max_obsidian_cost,
+      max_costs,
+      target,
     }
   }
+
+  /// Parse a blueprint such as `Blueprint 1: Each ore robot costs 4 ore. Each clay robot costs
+  /// 2 ore and 3 ore.` with any resource names. Resources are numbered in the order their
+  /// robots are listed, and the robots are tried from the last one listed while searching.
   fn from(input: &str) -> Blueprint {
-    let splits: Vec<&str> = input.split_whitespace().collect();
-    let id = splits[1].trim_end_matches(':').parse().unwrap();
-    let mut bot_costs = Vec::new();
-    bot_costs.push(BotCost {
-      robot: OreBot,
-      ore: splits[6].parse().unwrap(),
-      clay: 0,
-      obsidian: 0,
-    });
-    bot_costs.push(BotCost {
-      robot: ClayBot,
-      ore: splits[12].parse().unwrap(),
-      clay: 0,
-      obsidian: 0,
-    });
-    bot_costs.push(BotCost {
-      robot: ObsidianBot,
-      ore: splits[18].parse().unwrap(),
-      clay: splits[21].parse().unwrap(),
-      obsidian: 0,
-    });
-    bot_costs.push(BotCost {
-      robot: GeodeBot,
-      ore: splits[27].parse().unwrap(),
-      clay: 0,
-      obsidian: splits[30].parse().unwrap(),
-    });
+    let (name, robots) = input.split_once(':').expect("Expecting a blueprint id");
+    let id = name.trim_start_matches("Blueprint").trim().parse().unwrap();
+    let robots: Vec<(&str, Vec<(u32, &str)>)> = robots
+      .split_terminator('.')
+      .map(|robot| {
+        let splits: Vec<&str> = robot.split_whitespace().filter(|&s| s != "and").collect();
+        let costs = splits[4..].chunks(2)
+                               .map(|c| (c[0].parse().expect("Expecting a number for cost"), c[1]))
+                               .collect();
+        (splits[1], costs)
+      })
+      .collect();
+    let resources: Vec<String> = robots.iter().map(|(r, _)| r.to_string()).collect();
+    let mut bot_costs: Vec<BotCost> = robots.iter().enumerate().map(|(robot, (_, costs))| {
+      let mut bot_cost = BotCost::new(robot, vec![0; resources.len()]);
+      for (amount, resource) in costs {
+        let r = resources.iter().position(|n| n == resource)
+                         .expect("Expecting a robot for every resource");
+        bot_cost.costs[r] += amount;
+      }
+      bot_cost
+    }).collect();
     bot_costs.reverse();
-    Blueprint::new(id, bot_costs)
+    Blueprint::new(id, resources, bot_costs)
   }
 
-  fn simulate(&self, until_time: u32) -> u32 {
-    let mut max_geodes: u32 = 0;
-    let mut stack: VecDeque<Simulation> = VecDeque::new();
-    stack.push_back(Simulation::new());
-    while !stack.is_empty() {
-      let curr = stack.pop_front().unwrap();
-      max_geodes = max_geodes.max(curr.geode);
-      if curr.time == until_time {
-        continue;
+  /// The blueprint id multiplied by the most geodes that can be opened within the given time
+  pub fn quality_level(&self, until_time: u32) -> u32 {
+    self.id * self.simulate(until_time)
+  }
+
+  /// The most geodes that can be opened within the given time
+  pub fn simulate(&self, until_time: u32) -> u32 {
+    let search = self.search(until_time);
+    add_stat("nodes expanded", search.stats.expanded);
+    add_stat("pruned by bound", search.stats.pruned_bound);
+    add_stat("pruned as dominated", search.stats.pruned_dominated);
+    search.best
+  }
+
+  /// The build order that opens the most geodes within the given time
+  pub fn optimise(&self, until_time: u32) -> BuildPlan {
+    let search = self.search(until_time);
+    self.replay(&search.best_builds, until_time, search.stats)
+  }
+
+  fn search(&self, until_time: u32) -> Search<'_> {
+    let mut search = Search {
+      blueprint: self,
+      until_time,
+      best: 0,
+      best_builds: Vec::new(),
+      builds: Vec::new(),
+      seen: HashMap::new(),
+      stats: SearchStats::default(),
+    };
+    search.visit(&Simulation::new());
+    search
+  }
+
+  /// Wait until the robot can be afforded and build it. Returns `None` if it cannot be built
+  /// before the last minute, as it would not collect anything.
+  fn make_robot(&self, sim: &Simulation, cost: &BotCost, until_time: u32) -> Option<Simulation> {
+    if cost.costs.iter().zip(&sim.bots).any(|(&c, &b)| c > 0 && b < 1) {
+      return None;
+    }
+
+    let mut sim = sim.clone();
+    while sim.time + 1 < until_time {
+      if !sim.can_afford(cost) {
+        sim.gather();
+      } else {
+        // Create the bot
+        sim.build(cost);
+        return Some(sim);
       }
-      // Create the possible choices
+    }
+    None
+  }
+
+  /// The most geodes that could be opened from the simulation if every robot that can be
+  /// afforded was built each minute without paying for it. Stock and robots never fall behind
+  /// any real build order, so this never underestimates.
+  fn upper_bound(&self, sim: &Simulation, until_time: u32) -> u32 {
+    let mut sim = sim.clone();
+    while sim.time < until_time {
+      let bots = sim.bots;
       for cost in &self.bot_costs {
-        match cost.robot {
-          OreBot if curr.ore_bots >= self.max_ore_cost
-            || curr.ore / (until_time - curr.time) >= self.max_ore_cost - curr.ore_bots => continue,
-          ClayBot if curr.clay_bots >= self.max_clay_cost
-            || curr.clay / (until_time - curr.time) >= self.max_clay_cost - curr.clay_bots => continue,
-          ObsidianBot if curr.obsidian_bots >= self.max_obsidian_cost
-            || curr.obsidian / (until_time - curr.time) >= self.max_obsidian_cost - curr.obsidian_bots => continue,
-          _ => {}
+        if sim.can_afford(cost) {
+          sim.bots[cost.robot] += 1;
         }
-        match self.make_robot(&curr, cost, &until_time) {
-          Some(s) => {
-            if cost.robot == GeodeBot && curr.time + 1 == s.time {
-              stack.push_back(s);
-              break;
-            } else {
-              stack.push_back(s);
-            }
-          }
-          _ => {}
+      }
+      for (stock, bots) in sim.stock.iter_mut().zip(&bots) {
+        *stock += bots;
+      }
+      sim.time += 1;
+    }
+    sim.stock[self.target]
+  }
+
+  /// Replay the builds, given as the minute each robot is built in, minute by minute
+  fn replay(&self, builds: &[(u32, Resource)], until_time: u32, stats: SearchStats) -> BuildPlan {
+    let resources = self.resources.len();
+    let mut sim = Simulation::new();
+    let mut minutes = Vec::new();
+    let mut builds = builds.iter().peekable();
+    while sim.time < until_time {
+      let built = builds.next_if(|(time, _)| *time == sim.time + 1).map(|&(_, robot)| robot);
+      match built {
+        Some(robot) => {
+          let cost = self.bot_costs.iter().find(|bc| bc.robot == robot).unwrap();
+          sim.build(cost);
         }
+        None => sim.gather(),
       }
+      minutes.push(Minute {
+        minute: sim.time,
+        built,
+        bots: sim.bots[..resources].to_vec(),
+        stock: sim.stock[..resources].to_vec(),
+      });
+    }
+    BuildPlan {
+      resources: self.resources.clone(),
+      collected: sim.stock[self.target],
+      minutes,
+      stats,
     }
-    max_geodes
   }
+}
 
-  fn make_robot(&self, sim: &Simulation, cost: &BotCost, until_time: &u32) -> Option<Simulation> {
-    if (cost.obsidian > 0 && sim.obsidian_bots < 1)
-      || (cost.clay > 0 && sim.clay_bots < 1)
-      || (cost.ore > 0 && sim.ore_bots < 1) {
-      return None;
+/// What happened during one minute of a build plan, with the robots and stock at its end
+#[derive(Debug, Eq, PartialEq)]
+pub struct Minute {
+  pub minute: u32,
+  pub built: Option<Resource>,
+  pub bots: Vec<u32>,
+  pub stock: Vec<u32>,
+}
+
+/// The minute by minute plan that collects the most of the blueprint's last resource
+#[derive(Debug, Eq, PartialEq)]
+pub struct BuildPlan {
+  pub resources: Vec<String>,
+  pub collected: u32,
+  pub minutes: Vec<Minute>,
+  pub stats: SearchStats,
+}
+
+impl BuildPlan {
+  /// The robots built in order, with the minute they were built in
+  pub fn build_order(&self) -> Vec<(u32, Resource)> {
+    self.minutes.iter().filter_map(|m| m.built.map(|b| (m.minute, b))).collect()
+  }
+
+  fn write_counts(&self, f: &mut Formatter<'_>, counts: &[u32]) -> std::fmt::Result {
+    let counts: Vec<String> = counts.iter().zip(&self.resources)
+                                    .filter(|(&c, _)| c > 0)
+                                    .map(|(c, r)| format!("{} {}", c, r))
+                                    .collect();
+    write!(f, "{}", counts.join(", "))
+  }
+}
+
+impl Display for BuildPlan {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    for m in &self.minutes {
+      write!(f, "Minute {:2}: ", m.minute)?;
+      match m.built {
+        Some(r) => write!(f, "build {} robot", self.resources[r])?,
+        None => write!(f, "wait")?,
+      }
+      write!(f, "; robots ")?;
+      self.write_counts(f, &m.bots)?;
+      write!(f, "; stock ")?;
+      self.write_counts(f, &m.stock)?;
+      writeln!(f)?;
     }
+    Ok(())
+  }
+}
 
-    let mut sim = sim.clone();
-    while &sim.time < until_time {
-      if sim.obsidian < cost.obsidian
-        || sim.clay < cost.clay
-        || sim.ore < cost.ore {
-        sim.gather();
-      } else {
-        // Create the bot
-        sim.ore -= cost.ore;
-        sim.clay -= cost.clay;
-        sim.obsidian -= cost.obsidian;
-        sim.gather();
-        sim.add_bot(&cost.robot);
-        break;
+/// Counters describing how much work a search did
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct SearchStats {
+  pub expanded: u64,
+  pub pruned_bound: u64,
+  pub pruned_dominated: u64,
+}
+
+/// Depth first branch and bound over the next robot to build
+struct Search<'a> {
+  blueprint: &'a Blueprint,
+  until_time: u32,
+  best: u32,
+  best_builds: Vec<(u32, Resource)>,
+  builds: Vec<(u32, Resource)>,
+  // The stock of the last expanded simulation for a time and set of robots
+  seen: HashMap<(u32, [u32; MAX_RESOURCES]), [u32; MAX_RESOURCES]>,
+  stats: SearchStats,
+}
+
+impl Search<'_> {
+  fn visit(&mut self, sim: &Simulation) {
+    self.stats.expanded += 1;
+    let bp = self.blueprint;
+    let target = bp.target;
+    if sim.time >= self.until_time {
+      return;
+    }
+    // Build nothing more
+    let idle = sim.stock[target] + sim.bots[target] * (self.until_time - sim.time);
+    if idle > self.best {
+      self.best = idle;
+      self.best_builds = self.builds.clone();
+    }
+    for cost in &bp.bot_costs {
+      let r = cost.robot;
+      if r != target
+        && (sim.bots[r] >= bp.max_costs[r]
+        || sim.stock[r] / (self.until_time - sim.time) >= bp.max_costs[r] - sim.bots[r]) {
+        continue;
+      }
+      if let Some(s) = bp.make_robot(sim, cost, self.until_time) {
+        let immediate = r == target && sim.time + 1 == s.time;
+        if bp.upper_bound(&s, self.until_time) <= self.best {
+          self.stats.pruned_bound += 1;
+        } else if self.is_dominated(&s) {
+          self.stats.pruned_dominated += 1;
+        } else {
+          self.builds.push((s.time, r));
+          self.visit(&s);
+          self.builds.pop();
+        }
+        if immediate {
+          break;
+        }
       }
     }
-    Some(sim)
+  }
+
+  /// Whether a simulation with the same robots at the same time had at least as much stock
+  fn is_dominated(&mut self, sim: &Simulation) -> bool {
+    match self.seen.entry((sim.time, sim.bots)) {
+      Entry::Occupied(mut seen) => {
+        if seen.get().iter().zip(&sim.stock).all(|(seen, stock)| seen >= stock) {
+          return true;
+        }
+        seen.insert(sim.stock);
+      }
+      Entry::Vacant(seen) => {
+        seen.insert(sim.stock);
+      }
+    }
+    false
   }
 }
 
 #[derive(Clone, Debug)]
 struct Simulation {
   time: u32,
-  ore_bots: u32,
-  ore: u32,
-  clay_bots: u32,
-  clay: u32,
-  obsidian_bots: u32,
-  obsidian: u32,
-  geode_bots: u32,
-  geode: u32,
+  bots: [u32; MAX_RESOURCES],
+  stock: [u32; MAX_RESOURCES],
 }
 
 impl Simulation {
   fn new() -> Simulation {
+    let mut bots = [0; MAX_RESOURCES];
+    bots[0] = 1;
     Simulation {
       time: 0,
-      ore_bots: 1,
-      ore: 0,
-      clay_bots: 0,
-      clay: 0,
-      obsidian_bots: 0,
-      obsidian: 0,
-      geode_bots: 0,
-      geode: 0,
+      bots,
+      stock: [0; MAX_RESOURCES],
     }
   }
 
+  fn can_afford(&self, cost: &BotCost) -> bool {
+    cost.costs.iter().zip(&self.stock).all(|(c, s)| s >= c)
+  }
+
   fn gather(&mut self) {
     self.time += 1;
-    self.ore += self.ore_bots;
-    self.clay += self.clay_bots;
-    self.obsidian += self.obsidian_bots;
-    self.geode += self.geode_bots;
-  }
-
-  fn add_bot(&mut self, bot: &Robot) {
-    match bot {
-      OreBot => self.ore_bots += 1,
-      ClayBot => self.clay_bots += 1,
-      ObsidianBot => self.obsidian_bots += 1,
-      GeodeBot => self.geode_bots += 1,
+    for (stock, bots) in self.stock.iter_mut().zip(&self.bots) {
+      *stock += bots;
     }
   }
+
+  fn build(&mut self, cost: &BotCost) {
+    for (stock, c) in self.stock.iter_mut().zip(&cost.costs) {
+      *stock -= c;
+    }
+    self.gather();
+    self.bots[cost.robot] += 1;
+  }
 }
 
 #[cfg(test)]
 mod tests {
-  use crate::day19::{Blueprint, BotCost, generator, part1, part2};
-  use crate::day19::Robot::{ClayBot, GeodeBot, ObsidianBot, OreBot};
+  use crate::day19::{Blueprint, BotCost, BuildPlan, CLAY, first_n_product, GEODE, generator, OBSIDIAN,
+                     ORE, part1, part2, quality_level_sum, Simulation};
+  use crate::utils::take_stats;
 
   fn input() -> String {
     vec![
@@ -208,48 +400,143 @@ mod tests {
     ].join("\n")
   }
 
+  fn resources() -> Vec<String> {
+    ["ore", "clay", "obsidian", "geode"].iter().map(|r| r.to_string()).collect()
+  }
+
   #[test]
   fn test_generator() {
     let bps = generator(input().as_str());
     assert_eq!(2, bps.len());
-    let exp_bp = Blueprint::new(1,
+    let exp_bp = Blueprint::new(1, resources(),
                                 vec![
-                                  BotCost { robot: GeodeBot, ore: 2, clay: 0, obsidian: 7 },
-                                  BotCost { robot: ObsidianBot, ore: 3, clay: 14, obsidian: 0 },
-                                  BotCost { robot: ClayBot, ore: 2, clay: 0, obsidian: 0 },
-                                  BotCost { robot: OreBot, ore: 4, clay: 0, obsidian: 0 },
+                                  BotCost::new(GEODE, vec![2, 0, 7, 0]),
+                                  BotCost::new(OBSIDIAN, vec![3, 14, 0, 0]),
+                                  BotCost::new(CLAY, vec![2, 0, 0, 0]),
+                                  BotCost::new(ORE, vec![4, 0, 0, 0]),
                                 ]);
     assert_eq!(exp_bp, bps[0]);
-    let exp_bp = Blueprint::new(2,
+    let exp_bp = Blueprint::new(2, resources(),
                                 vec![
-                                  BotCost { robot: GeodeBot, ore: 3, clay: 0, obsidian: 12 },
-                                  BotCost { robot: ObsidianBot, ore: 3, clay: 8, obsidian: 0 },
-                                  BotCost { robot: ClayBot, ore: 3, clay: 0, obsidian: 0 },
-                                  BotCost { robot: OreBot, ore: 2, clay: 0, obsidian: 0 },
+                                  BotCost::new(GEODE, vec![3, 0, 12, 0]),
+                                  BotCost::new(OBSIDIAN, vec![3, 8, 0, 0]),
+                                  BotCost::new(CLAY, vec![3, 0, 0, 0]),
+                                  BotCost::new(ORE, vec![2, 0, 0, 0]),
                                 ]);
     assert_eq!(exp_bp, bps[1]);
   }
 
   #[test]
   fn test_simulation() {
-    let bp = Blueprint::new(1,
+    let bp = Blueprint::new(1, resources(),
                             vec![
-                              BotCost { robot: GeodeBot, ore: 2, clay: 0, obsidian: 7 },
-                              BotCost { robot: ObsidianBot, ore: 3, clay: 14, obsidian: 0 },
-                              BotCost { robot: ClayBot, ore: 2, clay: 0, obsidian: 0 },
-                              BotCost { robot: OreBot, ore: 4, clay: 0, obsidian: 0 },
+                              BotCost::new(GEODE, vec![2, 0, 7, 0]),
+                              BotCost::new(OBSIDIAN, vec![3, 14, 0, 0]),
+                              BotCost::new(CLAY, vec![2, 0, 0, 0]),
+                              BotCost::new(ORE, vec![4, 0, 0, 0]),
                             ]);
     assert_eq!(9, bp.simulate(24));
-    let bp = Blueprint::new(2,
+    let bp = Blueprint::new(2, resources(),
                             vec![
-                              BotCost { robot: GeodeBot, ore: 3, clay: 0, obsidian: 12 },
-                              BotCost { robot: ObsidianBot, ore: 3, clay: 8, obsidian: 0 },
-                              BotCost { robot: ClayBot, ore: 3, clay: 0, obsidian: 0 },
-                              BotCost { robot: OreBot, ore: 2, clay: 0, obsidian: 0 },
+                              BotCost::new(GEODE, vec![3, 0, 12, 0]),
+                              BotCost::new(OBSIDIAN, vec![3, 8, 0, 0]),
+                              BotCost::new(CLAY, vec![3, 0, 0, 0]),
+                              BotCost::new(ORE, vec![2, 0, 0, 0]),
                             ]);
     assert_eq!(12, bp.simulate(24));
   }
 
+  fn check_plan(bp: &Blueprint, plan: &BuildPlan, until_time: u32) {
+    assert_eq!(until_time as usize, plan.minutes.len());
+    assert_eq!(plan.collected, *plan.minutes.last().unwrap().stock.last().unwrap());
+    assert_eq!(bp.simulate(until_time), plan.collected);
+    for (i, m) in plan.minutes.iter().enumerate() {
+      assert_eq!(i as u32 + 1, m.minute);
+      let built: u32 = m.bots.iter().sum::<u32>() - 1;
+      assert_eq!(built as usize, plan.build_order().iter().filter(|(t, _)| *t <= m.minute).count());
+    }
+  }
+
+  #[test]
+  fn test_optimise() {
+    let bps = generator(input().as_str());
+    let plan = bps[0].optimise(24);
+    check_plan(&bps[0], &plan, 24);
+    assert_eq!(9, plan.collected);
+    // The worked example from the puzzle
+    assert_eq!(vec![(3, CLAY), (5, CLAY), (7, CLAY), (11, OBSIDIAN), (12, CLAY), (15, OBSIDIAN),
+                    (18, GEODE), (21, GEODE)], plan.build_order());
+    assert_eq!(&vec![1, 4, 2, 2], &plan.minutes[23].bots);
+    assert_eq!(&vec![6, 41, 8, 9], &plan.minutes[23].stock);
+    let text = plan.to_string();
+    assert_eq!(24, text.lines().count());
+    assert_eq!("Minute  3: build clay robot; robots 1 ore, 1 clay; stock 1 ore",
+               text.lines().nth(2).unwrap());
+
+    check_plan(&bps[1], &bps[1].optimise(24), 24);
+    check_plan(&bps[1], &bps[1].optimise(10), 10);
+    assert_eq!(0, bps[1].optimise(10).collected);
+
+    assert_eq!(0, bps[0].simulate(0));
+    assert_eq!(0, bps[0].optimise(0).collected);
+    assert_eq!(0, quality_level_sum(&bps, 0));
+  }
+
+  #[test]
+  fn test_custom_resources() {
+    let bps = generator(
+      "Blueprint 7: Each wood robot costs 2 wood. Each stone robot costs 3 wood. \
+       Each gold robot costs 1 wood and 2 stone and 1 wood.");
+    let bp = &bps[0];
+    assert_eq!(vec!["wood", "stone", "gold"], bp.resources);
+    assert_eq!(&BotCost::new(2, vec![2, 2, 0]), &bp.bot_costs[0]);
+    let plan = bp.optimise(12);
+    check_plan(bp, &plan, 12);
+    assert_eq!(plan.collected * 7, bp.quality_level(12));
+    assert!(plan.collected > 0);
+  }
+
+  #[test]
+  fn test_search_stats() {
+    let bps = generator(input().as_str());
+    let plan = bps[0].optimise(24);
+    assert!(plan.stats.expanded > 0);
+    assert!(plan.stats.pruned_bound > 0);
+    assert_eq!(plan.stats, bps[0].optimise(24).stats);
+
+    take_stats();
+    bps[0].simulate(24);
+    let stats = take_stats();
+    assert_eq!(("nodes expanded".to_string(), plan.stats.expanded), stats[0]);
+    assert_eq!(("pruned by bound".to_string(), plan.stats.pruned_bound), stats[2]);
+  }
+
+  #[test]
+  fn test_upper_bound() {
+    let bps = generator(input().as_str());
+    for bp in &bps {
+      for until_time in [10, 18, 24] {
+        assert!(bp.upper_bound(&Simulation::new(), until_time) >= bp.simulate(until_time));
+      }
+    }
+    let bp = &bps[0];
+    let mut sim = Simulation::new();
+    sim.time = 20;
+    sim.bots[GEODE] = 2;
+    sim.stock[GEODE] = 3;
+    // Without obsidian robots no more geode robots can ever be built
+    assert_eq!(3 + 2 * 4, bp.upper_bound(&sim, 24));
+  }
+
+  #[test]
+  fn test_aggregations() {
+    let bps = generator(input().as_str());
+    assert_eq!(33, quality_level_sum(&bps, 24));
+    assert_eq!(9 * 12, first_n_product(&bps, 2, 24));
+    assert_eq!(9, first_n_product(&bps, 1, 24));
+    assert_eq!(1, first_n_product(&bps, 0, 24));
+  }
+
   #[test]
   fn test_part1() {
     let bps = generator(input().as_str());
@@ -261,4 +548,29 @@ mod tests {
     let bps = generator(input().as_str());
     assert_eq!(62 * 56, part2(&bps));
   }
-}
\ No newline at end of file
+
+  #[test]
+  #[should_panic(expected = "Expecting at least one resource")]
+  fn test_no_resources() {
+    Blueprint::new(1, vec![], vec![]);
+  }
+
+  #[test]
+  #[should_panic(expected = "Expecting at most 8 resources, got 9")]
+  fn test_too_many_resources() {
+    Blueprint::new(1, (0..9).map(|r| r.to_string()).collect(), vec![]);
+  }
+
+  #[test]
+  #[should_panic(expected = "Expecting a cost for each resource for robot 1")]
+  fn test_missing_cost() {
+    Blueprint::new(1, vec!["ore".to_string(), "clay".to_string()],
+                   vec![BotCost::new(0, vec![1, 0]), BotCost::new(1, vec![2])]);
+  }
+
+  #[test]
+  #[should_panic(expected = "Expecting a robot for one of the 2 resources, got 2")]
+  fn test_unknown_robot() {
+    Blueprint::new(1, vec!["ore".to_string(), "clay".to_string()], vec![BotCost::new(2, vec![1, 0])]);
+  }
+}
@@ -1,6 +1,7 @@
 use std::cell::{RefCell};
 
-const DECRYPTION_KEY: i64 = 811589153;
+pub const DECRYPTION_KEY: i64 = 811589153;
+pub const GROVE_OFFSETS: [usize; 3] = [1000, 2000, 3000];
 
 pub fn generator(input: &str) -> Vec<i64> {
   input.lines()
@@ -9,38 +10,68 @@ pub fn generator(input: &str) -> Vec<i64> {
 }
 
 pub fn part1(numbers: &Vec<i64>) -> i64 {
-  let mut numbers = Numbers::from(numbers.clone());
-  numbers.mix();
-  let numbers = numbers.to_vec();
-  let zero_pos = numbers.iter().position(|n| n == &0).unwrap();
-  let mut result = 0;
-  for i in [1000, 2000, 3000] {
-    result += numbers[(zero_pos + i) % numbers.len()];
-  }
-  result
+  decrypt::<Treap>(numbers, 1, 1, &GROVE_OFFSETS).coordinates.iter().sum()
 }
 
 pub fn part2(numbers: &Vec<i64>) -> i64 {
-  let mut numbers = Numbers::from(numbers.iter().map(|n| n * DECRYPTION_KEY).collect());
-  for _ in 0..10 {
-    numbers.mix();
+  decrypt::<Treap>(numbers, DECRYPTION_KEY, 10, &GROVE_OFFSETS).coordinates.iter().sum()
+}
+
+/// The mixed numbers, starting from 0, and the numbers at each offset after the 0
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Decryption {
+  pub sequence: Vec<i64>,
+  pub coordinates: Vec<i64>,
+}
+
+/// Multiply the numbers by the key, mix them the given number of rounds with the mixer and read
+/// the grove coordinates at the offsets. There are no coordinates when there are no numbers.
+pub fn decrypt<M: Mixer>(numbers: &[i64], key: i64, rounds: usize, offsets: &[usize]) -> Decryption {
+  decrypt_with::<M, _>(numbers, key, rounds, offsets, |_, _| {})
+}
+
+/// As [decrypt], calling back with the round number and the sequence, starting from 0, after each
+/// round of mixing
+pub fn decrypt_with<M: Mixer, F: FnMut(usize, &[i64])>(numbers: &[i64], key: i64, rounds: usize,
+                                                       offsets: &[usize], mut observer: F) -> Decryption {
+  if numbers.is_empty() {
+    return Decryption { sequence: Vec::new(), coordinates: Vec::new() };
   }
-  let numbers = numbers.to_vec();
-  let zero_pos = numbers.iter().position(|n| n == &0).unwrap();
-  let mut result = 0;
-  for i in [1000, 2000, 3000] {
-    result += numbers[(zero_pos + i) % numbers.len()];
+  let mut mixer = M::new(numbers.iter().map(|n| n * key).collect());
+  for round in 1..=rounds {
+    mixer.mix();
+    observer(round, &from_zero(mixer.to_vec()));
   }
-  result
+  let sequence = from_zero(mixer.to_vec());
+  let coordinates = offsets.iter().map(|o| sequence[o % sequence.len()]).collect();
+  Decryption { sequence, coordinates }
+}
+
+fn from_zero(mut numbers: Vec<i64>) -> Vec<i64> {
+  let zero_pos = numbers.iter().position(|n| n == &0).expect("Expecting a 0 among the numbers");
+  numbers.rotate_left(zero_pos);
+  numbers
+}
+
+/// A way of keeping the numbers in order while they are mixed
+pub trait Mixer {
+  fn new(numbers: Vec<i64>) -> Self;
+
+  /// Move every number once, in their original order
+  fn mix(&mut self);
+
+  /// The numbers in their current order, starting with the first of the original numbers
+  fn to_vec(&self) -> Vec<i64>;
 }
 
-struct Numbers {
+/// Numbers in a circular doubly linked list, walking the list for each move
+pub struct Numbers {
   numbers: Vec<i64>,
   links: Vec<RefCell<(usize, usize)>>,
 }
 
-impl Numbers {
-  fn from(numbers: Vec<i64>) -> Numbers {
+impl Mixer for Numbers {
+  fn new(numbers: Vec<i64>) -> Numbers {
     let mut links = Vec::new();
     for i in 0..numbers.len() {
       links.push(RefCell::from(((i as i64 - 1).rem_euclid(numbers.len() as i64) as usize,
@@ -57,6 +88,19 @@ impl Numbers {
     }
   }
 
+  fn to_vec(&self) -> Vec<i64> {
+    let mut result = Vec::new();
+    result.push(self.numbers[0]);
+    let mut next_idx = self.links[0].borrow().1;
+    while next_idx != 0 {
+      result.push(self.numbers[next_idx]);
+      next_idx = self.links[next_idx].borrow().1;
+    }
+    result
+  }
+}
+
+impl Numbers {
   fn move_steps(&mut self, i: usize) {
     let mut steps = self.numbers[i] % (self.numbers.len() - 1) as i64;
     let mut this = self.links[i].borrow_mut();
@@ -90,22 +134,175 @@ impl Numbers {
       right.0 = i;
     }
   }
+}
+
+const NIL: usize = usize::MAX;
+
+/// Numbers in an implicit treap ordered by their current position, with parent links so a
+/// number's position can be found from its node. Each move takes O(log n).
+pub struct Treap {
+  numbers: Vec<i64>,
+  left: Vec<usize>,
+  right: Vec<usize>,
+  parent: Vec<usize>,
+  size: Vec<usize>,
+  priority: Vec<u64>,
+  root: usize,
+}
+
+impl Mixer for Treap {
+  fn new(numbers: Vec<i64>) -> Treap {
+    let n = numbers.len();
+    // A fixed xorshift sequence keeps the shape of the tree the same from run to run
+    let mut seed: u64 = 0x2545_f491_4f6c_dd1d;
+    let priority = (0..n).map(|_| {
+      seed ^= seed << 13;
+      seed ^= seed >> 7;
+      seed ^= seed << 17;
+      seed
+    }).collect();
+    let mut treap = Treap {
+      numbers,
+      left: vec![NIL; n],
+      right: vec![NIL; n],
+      parent: vec![NIL; n],
+      size: vec![1; n],
+      priority,
+      root: NIL,
+    };
+    for i in 0..n {
+      treap.root = treap.merge(treap.root, i);
+    }
+    treap.detach(treap.root);
+    treap
+  }
+
+  fn mix(&mut self) {
+    for i in 0..self.numbers.len() {
+      self.move_steps(i);
+    }
+  }
 
   fn to_vec(&self) -> Vec<i64> {
-    let mut result = Vec::new();
-    result.push(self.numbers[0]);
-    let mut next_idx = self.links[0].borrow().1;
-    while next_idx != 0 {
-      result.push(self.numbers[next_idx]);
-      next_idx = self.links[next_idx].borrow().1;
+    let mut order = Vec::with_capacity(self.numbers.len());
+    let mut stack = Vec::new();
+    let mut curr = self.root;
+    while curr != NIL || !stack.is_empty() {
+      while curr != NIL {
+        stack.push(curr);
+        curr = self.left[curr];
+      }
+      let node = stack.pop().unwrap();
+      order.push(node);
+      curr = self.right[node];
     }
-    result
+    let start = order.iter().position(|i| *i == 0).unwrap_or(0);
+    order.rotate_left(start);
+    order.iter().map(|i| self.numbers[*i]).collect()
+  }
+}
+
+impl Treap {
+  fn size_of(&self, t: usize) -> usize {
+    if t == NIL { 0 } else { self.size[t] }
+  }
+
+  fn update(&mut self, t: usize) {
+    self.size[t] = 1 + self.size_of(self.left[t]) + self.size_of(self.right[t]);
+    for child in [self.left[t], self.right[t]] {
+      if child != NIL {
+        self.parent[child] = t;
+      }
+    }
+  }
+
+  fn detach(&mut self, t: usize) {
+    if t != NIL {
+      self.parent[t] = NIL;
+    }
+  }
+
+  /// Split into the first k nodes and the rest
+  fn split(&mut self, t: usize, k: usize) -> (usize, usize) {
+    if t == NIL {
+      return (NIL, NIL);
+    }
+    let left_size = self.size_of(self.left[t]);
+    if left_size < k {
+      let (a, b) = self.split(self.right[t], k - left_size - 1);
+      self.right[t] = a;
+      self.update(t);
+      (t, b)
+    } else {
+      let (a, b) = self.split(self.left[t], k);
+      self.left[t] = b;
+      self.update(t);
+      (a, t)
+    }
+  }
+
+  fn merge(&mut self, a: usize, b: usize) -> usize {
+    if a == NIL {
+      return b;
+    }
+    if b == NIL {
+      return a;
+    }
+    if self.priority[a] > self.priority[b] {
+      self.right[a] = self.merge(self.right[a], b);
+      self.update(a);
+      a
+    } else {
+      self.left[b] = self.merge(a, self.left[b]);
+      self.update(b);
+      b
+    }
+  }
+
+  /// The current position of the number at the given original index
+  fn position(&self, i: usize) -> usize {
+    let mut pos = self.size_of(self.left[i]);
+    let mut curr = i;
+    while self.parent[curr] != NIL {
+      let p = self.parent[curr];
+      if self.right[p] == curr {
+        pos += self.size_of(self.left[p]) + 1;
+      }
+      curr = p;
+    }
+    pos
+  }
+
+  fn move_steps(&mut self, i: usize) {
+    let len = self.numbers.len();
+    if len < 2 {
+      return;
+    }
+    let pos = self.position(i);
+    let (before, rest) = self.split(self.root, pos);
+    let (node, after) = self.split(rest, 1);
+    debug_assert_eq!(i, node);
+    self.detach(before);
+    self.detach(after);
+    self.detach(node);
+    let rest = self.merge(before, after);
+    self.detach(rest);
+    let new_pos = (pos as i64 + self.numbers[i]).rem_euclid(len as i64 - 1) as usize;
+    let (before, after) = self.split(rest, new_pos);
+    self.detach(before);
+    self.detach(after);
+    let before = self.merge(before, node);
+    self.root = self.merge(before, after);
+    self.detach(self.root);
   }
 }
 
 #[cfg(test)]
 mod tests {
-  use crate::day20::{generator, Numbers, part1, part2};
+  use rand::{Rng, SeedableRng};
+  use rand::rngs::StdRng;
+  use crate::day20::{decrypt, decrypt_with, DECRYPTION_KEY, Decryption, generator, GROVE_OFFSETS, Mixer, Numbers,
+                     part1, part2, Treap};
 
   fn input() -> String {
     vec![
@@ -135,7 +332,7 @@ mod tests {
     assert_eq!(&1, numbers.first().unwrap());
     assert_eq!(&4, numbers.last().unwrap());
 
-    let mut numbers = Numbers::from(numbers);
+    let mut numbers = Numbers::new(numbers);
     assert_eq!(vec![1, 2, -3, 3, -2, 0, 4], numbers.to_vec());
     numbers.move_steps(0);
     assert_eq!(to_pairs(vec![2, 1, -3, 3, -2, 0, 4]), to_pairs(numbers.to_vec()));
@@ -164,4 +361,52 @@ mod tests {
     let numbers = generator(input().as_str());
     assert_eq!(1623178306, part2(&numbers));
   }
-}
\ No newline at end of file
+
+  #[test]
+  fn test_treap() {
+    let numbers = generator(input().as_str());
+    let mut treap = Treap::new(numbers.clone());
+    assert_eq!(numbers, treap.to_vec());
+    treap.move_steps(0);
+    assert_eq!(to_pairs(vec![2, 1, -3, 3, -2, 0, 4]), to_pairs(treap.to_vec()));
+    treap.move_steps(2);
+    assert_eq!(to_pairs(vec![2, 1, 3, -2, 0, -3, 4]), to_pairs(treap.to_vec()));
+  }
+
+  #[test]
+  fn test_backends_agree() {
+    let mut rng = StdRng::seed_from_u64(20);
+    for _ in 0..200 {
+      let len = rng.gen_range(2..60);
+      let numbers: Vec<i64> = (0..len).map(|_| rng.gen_range(-1000..1000)).collect();
+      let mut list = Numbers::new(numbers.clone());
+      let mut treap = Treap::new(numbers);
+      for _ in 0..rng.gen_range(1..4) {
+        list.mix();
+        treap.mix();
+        assert_eq!(list.to_vec(), treap.to_vec());
+      }
+    }
+  }
+
+  #[test]
+  fn test_decrypt() {
+    let numbers = generator(input().as_str());
+    assert_eq!(Decryption { sequence: vec![0, 3, -2, 1, 2, -3, 4], coordinates: vec![4, -3, 2] },
+               decrypt::<Treap>(&numbers, 1, 1, &GROVE_OFFSETS));
+    assert_eq!(vec![0, -2, 1], decrypt::<Treap>(&numbers, 1, 1, &[0, 2, 3]).coordinates);
+    assert_eq!(vec![0, 4, 1, 2, -3, 3, -2], decrypt::<Treap>(&numbers, 1, 0, &[]).sequence);
+    assert_eq!(decrypt::<Treap>(&numbers, DECRYPTION_KEY, 10, &GROVE_OFFSETS),
+               decrypt::<Numbers>(&numbers, DECRYPTION_KEY, 10, &GROVE_OFFSETS));
+    assert_eq!(Decryption { sequence: vec![], coordinates: vec![] }, decrypt::<Treap>(&[], 1, 1, &GROVE_OFFSETS));
+
+    let mut rounds = Vec::new();
+    let decryption = decrypt_with::<Treap, _>(&numbers, DECRYPTION_KEY, 10, &GROVE_OFFSETS, |round, sequence| {
+      rounds.push((round, sequence.to_vec()));
+    });
+    assert_eq!(vec![811589153, 2434767459, -1623178306], decryption.coordinates);
+    assert_eq!(10, rounds.len());
+    assert_eq!((1, vec![0, -2434767459, 3246356612, -1623178306, 2434767459, 1623178306, 811589153]), rounds[0]);
+    assert_eq!((10, vec![0, -2434767459, 1623178306, 3246356612, -1623178306, 2434767459, 811589153]), rounds[9]);
+  }
+}
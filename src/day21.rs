@@ -1,4 +1,9 @@
+use std::cmp::Ordering;
 use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::ops;
+use num_bigint::BigInt;
+use num_traits::{One, Signed, ToPrimitive, Zero};
 use crate::day21::Node::{Num, Op};
 use crate::day21::Operator::{Add, Sub, Mul, Div};
 
@@ -14,27 +19,34 @@ pub fn generator(input: &str) -> Node {
 }
 
 pub fn part1(root: &Node) -> i64 {
-  root.evaluate()
+  root.evaluate().and_then(|n| n.to_integer())
+    .unwrap_or_else(|e| panic!("Expecting root to yell a number: {e}"))
 }
 
 pub fn part2(root: &Node) -> i64 {
+  human_value(root).unwrap_or_else(|e| panic!("Expecting a number for humn to yell: {e}"))
+}
+
+/// The number to yell so that both sides of the root are equal, which must be a whole number
+pub fn human_value(root: &Node) -> Result<i64, MathError> {
   let mut path = Vec::new();
   root.path_to_human(&mut path);
-  match (root, path.pop().unwrap()) {
+  let value = match (root, path.pop().unwrap()) {
     (Op(_, l, r), '<') => {
-      l.compute_human_value(&mut path, r.evaluate())
+      l.compute_human_value(&mut path, r.evaluate()?)
     }
     (Op(_, l, r), '>') => {
-      r.compute_human_value(&mut path, l.evaluate())
+      r.compute_human_value(&mut path, l.evaluate()?)
     }
     _ => panic!("Unexpected path"),
-  }
+  }?;
+  value.to_integer()
 }
 
 fn make_node(node: &str, map: &HashMap<&str, Vec<&str>>) -> Node {
   let tokens = &map[node];
   match tokens[..] {
-    [n] => Num(node == HUMAN_CODE, n.parse().unwrap()),
+    [n] => Num(node.to_string(), n.parse().unwrap()),
     [left, op, right] => {
       Op(Operator::from(op), Box::new(make_node(left, map)), Box::new(make_node(right, map)))
     }
@@ -59,55 +71,72 @@ impl Operator {
       _ => panic!("Unexpected operation {input}"),
     }
   }
-  fn evaluate(&self, left: i64, right: i64) -> i64 {
+  fn evaluate(&self, left: &Rational, right: &Rational) -> Result<Rational, MathError> {
+    match self {
+      Add => Ok(left + right),
+      Sub => Ok(left - right),
+      Mul => Ok(left * right),
+      Div => left.checked_div(right),
+    }
+  }
+
+  fn evaluate_left(&self, expect: &Rational, right: &Rational) -> Result<Rational, MathError> {
     match self {
-      Add => left + right,
-      Sub => left - right,
-      Mul => left * right,
-      Div => left / right,
+      Add => Ok(expect - right),
+      Sub => Ok(expect + right),
+      Mul => expect.checked_div(right),
+      Div => Ok(expect * right),
     }
   }
 
-  fn evaluate_left(&self, expect: i64, right: i64) -> i64 {
+  fn evaluate_right(&self, expect: &Rational, left: &Rational) -> Result<Rational, MathError> {
     match self {
-      Add => expect - right,
-      Sub => expect + right,
-      Mul => expect / right,
-      Div => expect * right,
+      Add => Ok(expect - left),
+      Sub => Ok(left - expect),
+      Mul => expect.checked_div(left),
+      Div => left.checked_div(expect),
     }
   }
 
-  fn evaluate_right(&self, expect: i64, left: i64) -> i64 {
+  fn evaluate_symbolic(&self, left: Polynomial, right: Polynomial) -> Result<Polynomial, SymbolicError> {
     match self {
-      Add => expect - left,
-      Sub => left - expect,
-      Mul => expect / left,
-      Div => left / expect,
+      Add => Ok(&left + &right),
+      Sub => Ok(&left - &right),
+      Mul => Ok(&left * &right),
+      Div => match right.degree() {
+        None => Err(SymbolicError::DivisionByZero),
+        Some(0) => Ok(left.divide(&right.coefficients[0])),
+        Some(_) => Err(SymbolicError::NotPolynomial),
+      }
     }
   }
 }
 
 pub enum Node {
   Op(Operator, Box<Node>, Box<Node>),
-  Num(bool, i64),
+  Num(String, i64),
 }
 
 impl Node {
-  fn evaluate(&self) -> i64 {
+  fn evaluate(&self) -> Result<Rational, MathError> {
     match self {
-      Op(o, o_l, o_r) => o.evaluate(o_l.evaluate(), o_r.evaluate()),
-      Num(_, n) => *n
+      Op(o, o_l, o_r) => o.evaluate(&o_l.evaluate()?, &o_r.evaluate()?),
+      Num(_, n) => Ok(Rational::from(*n))
     }
   }
 
   fn path_to_human(&self, path: &mut Vec<char>) -> bool {
+    self.path_to(HUMAN_CODE, path)
+  }
+
+  fn path_to(&self, name: &str, path: &mut Vec<char>) -> bool {
     match self {
-      Num(x, _) => *x,
+      Num(x, _) => x == name,
       Op(_, l, r) => {
-        if l.path_to_human(path) {
+        if l.path_to(name, path) {
           path.push('<');
           true
-        } else if r.path_to_human(path) {
+        } else if r.path_to(name, path) {
           path.push('>');
           true
         } else {
@@ -117,25 +146,372 @@ impl Node {
     }
   }
 
-  fn compute_human_value(&self, path: &mut Vec<char>, expect: i64) -> i64 {
+  fn compute_human_value(&self, path: &mut Vec<char>, expect: Rational) -> Result<Rational, MathError> {
     if path.is_empty() {
-      return expect;
+      return Ok(expect);
     }
     match (self, path.pop().unwrap()) {
       (Op(o, l, r), '<') => {
-        l.compute_human_value(path, o.evaluate_left(expect, r.evaluate()))
+        l.compute_human_value(path, o.evaluate_left(&expect, &r.evaluate()?)?)
       }
       (Op(o, l, r), '>') => {
-        r.compute_human_value(path, o.evaluate_right(expect, l.evaluate()))
+        r.compute_human_value(path, o.evaluate_right(&expect, &l.evaluate()?)?)
       }
       _ => panic!("Unexpected path"),
     }
   }
+
+  /// The value of the tree as a polynomial in the named leaf, treating every other leaf as
+  /// the number it yells
+  fn to_polynomial(&self, unknown: &str) -> Result<Polynomial, SymbolicError> {
+    match self {
+      Num(name, _) if name == unknown => Ok(Polynomial::unknown()),
+      Num(_, n) => Ok(Polynomial::constant(Rational::from(*n))),
+      Op(o, l, r) => o.evaluate_symbolic(l.to_polynomial(unknown)?, r.to_polynomial(unknown)?),
+    }
+  }
+}
+
+/// Why a tree could not be turned into an equation or solved
+#[derive(Debug, Eq, PartialEq)]
+pub enum SymbolicError {
+  /// The tree has no leaf with the name being solved for
+  UnknownVariable(String),
+  /// The root yells a number rather than comparing two monkeys
+  NotAnEquation,
+  /// The unknown ends up in a divisor, so the tree is not a polynomial
+  NotPolynomial,
+  DivisionByZero,
+  /// Only equations up to degree one can be solved
+  NonLinear(usize),
+}
+
+/// How many values of the unknown make both sides of an equation equal
+#[derive(Debug, Eq, PartialEq)]
+pub enum Solution {
+  Unique(Rational),
+  None,
+  Infinite,
+}
+
+/// Why a number could not be computed exactly
+#[derive(Debug, Eq, PartialEq)]
+pub enum MathError {
+  DivisionByZero,
+  /// The exact answer has a fractional part
+  NotInteger(Rational),
+  /// The exact answer does not fit in an `i64`
+  OutOfRange(Rational),
+}
+
+impl Display for MathError {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    match self {
+      MathError::DivisionByZero => write!(f, "division by zero"),
+      MathError::NotInteger(n) => write!(f, "{} is not a whole number", n),
+      MathError::OutOfRange(n) => write!(f, "{} does not fit in 64 bits", n),
+    }
+  }
+}
+
+/// An exact fraction of big integers kept in lowest terms with a positive denominator, so
+/// arithmetic never truncates or overflows
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Rational {
+  numerator: BigInt,
+  denominator: BigInt,
+}
+
+fn gcd(mut a: BigInt, mut b: BigInt) -> BigInt {
+  while !b.is_zero() {
+    let r = &a % &b;
+    a = b;
+    b = r;
+  }
+  a.abs()
+}
+
+impl Rational {
+  /// Panics if the denominator is zero
+  pub fn new<N: Into<BigInt>>(numerator: N, denominator: N) -> Rational {
+    let (numerator, denominator) = (numerator.into(), denominator.into());
+    if denominator.is_zero() {
+      panic!("Denominator cannot be zero");
+    }
+    let mut g = gcd(numerator.clone(), denominator.clone());
+    if denominator.is_negative() {
+      g = -g;
+    }
+    Rational { numerator: numerator / &g, denominator: denominator / &g }
+  }
+
+  pub fn is_zero(&self) -> bool {
+    self.numerator.is_zero()
+  }
+
+  pub fn is_negative(&self) -> bool {
+    self.numerator.is_negative()
+  }
+
+  pub fn is_integer(&self) -> bool {
+    self.denominator.is_one()
+  }
+
+  pub fn checked_div(&self, rhs: &Rational) -> Result<Rational, MathError> {
+    if rhs.is_zero() {
+      return Err(MathError::DivisionByZero);
+    }
+    Ok(Rational::new(&self.numerator * &rhs.denominator, &self.denominator * &rhs.numerator))
+  }
+
+  /// The value as an `i64` if it is a whole number that fits
+  pub fn to_integer(&self) -> Result<i64, MathError> {
+    if !self.is_integer() {
+      return Err(MathError::NotInteger(self.clone()));
+    }
+    self.numerator.to_i64().ok_or_else(|| MathError::OutOfRange(self.clone()))
+  }
+}
+
+impl From<i64> for Rational {
+  fn from(n: i64) -> Self {
+    Rational { numerator: BigInt::from(n), denominator: BigInt::one() }
+  }
+}
+
+impl Display for Rational {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    if self.is_integer() {
+      write!(f, "{}", self.numerator)
+    } else {
+      write!(f, "{}/{}", self.numerator, self.denominator)
+    }
+  }
+}
+
+impl Ord for Rational {
+  fn cmp(&self, other: &Self) -> Ordering {
+    (&self.numerator * &other.denominator).cmp(&(&other.numerator * &self.denominator))
+  }
+}
+
+impl PartialOrd for Rational {
+  fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+    Some(self.cmp(other))
+  }
+}
+
+impl ops::Add for &Rational {
+  type Output = Rational;
+
+  fn add(self, rhs: Self) -> Self::Output {
+    Rational::new(&self.numerator * &rhs.denominator + &rhs.numerator * &self.denominator,
+                  &self.denominator * &rhs.denominator)
+  }
+}
+
+impl ops::Sub for &Rational {
+  type Output = Rational;
+
+  fn sub(self, rhs: Self) -> Self::Output {
+    self + &-rhs
+  }
+}
+
+impl ops::Mul for &Rational {
+  type Output = Rational;
+
+  fn mul(self, rhs: Self) -> Self::Output {
+    Rational::new(&self.numerator * &rhs.numerator, &self.denominator * &rhs.denominator)
+  }
+}
+
+/// Panics when dividing by zero, see `Rational::checked_div`
+impl ops::Div for &Rational {
+  type Output = Rational;
+
+  fn div(self, rhs: Self) -> Self::Output {
+    self.checked_div(rhs).expect("Cannot divide by zero")
+  }
+}
+
+impl ops::Neg for &Rational {
+  type Output = Rational;
+
+  fn neg(self) -> Self::Output {
+    Rational { numerator: -&self.numerator, denominator: self.denominator.clone() }
+  }
+}
+
+/// A polynomial in a single unknown with its coefficients from the lowest power up. Trailing
+/// zero coefficients are dropped so the zero polynomial has none.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Polynomial {
+  coefficients: Vec<Rational>,
+}
+
+impl Polynomial {
+  fn new(mut coefficients: Vec<Rational>) -> Polynomial {
+    while coefficients.last().is_some_and(|c| c.is_zero()) {
+      coefficients.pop();
+    }
+    Polynomial { coefficients }
+  }
+
+  fn constant(c: Rational) -> Polynomial {
+    Polynomial::new(vec![c])
+  }
+
+  fn unknown() -> Polynomial {
+    Polynomial::new(vec![Rational::from(0), Rational::from(1)])
+  }
+
+  /// The highest power of the unknown, or `None` for the zero polynomial
+  pub fn degree(&self) -> Option<usize> {
+    self.coefficients.len().checked_sub(1)
+  }
+
+  /// The coefficient of the given power of the unknown
+  pub fn coefficient(&self, power: usize) -> Rational {
+    self.coefficients.get(power).cloned().unwrap_or(Rational::from(0))
+  }
+
+  fn divide(&self, divisor: &Rational) -> Polynomial {
+    Polynomial::new(self.coefficients.iter().map(|c| c / divisor).collect())
+  }
+
+  /// Write the polynomial from the highest power down, naming the unknown
+  fn write(&self, f: &mut Formatter<'_>, unknown: &str) -> std::fmt::Result {
+    let mut first = true;
+    for (power, c) in self.coefficients.iter().enumerate().rev() {
+      if c.is_zero() {
+        continue;
+      }
+      let magnitude = if c.is_negative() { -c } else { c.clone() };
+      match (first, c.is_negative()) {
+        (true, true) => write!(f, "-")?,
+        (false, true) => write!(f, " - ")?,
+        (false, false) => write!(f, " + ")?,
+        (true, false) => {}
+      }
+      first = false;
+      if power == 0 {
+        write!(f, "{}", magnitude)?;
+        continue;
+      }
+      if magnitude != Rational::from(1) {
+        write!(f, "{} * ", magnitude)?;
+      }
+      write!(f, "{}", unknown)?;
+      if power > 1 {
+        write!(f, "^{}", power)?;
+      }
+    }
+    if first {
+      write!(f, "0")?;
+    }
+    Ok(())
+  }
+}
+
+impl ops::Add for &Polynomial {
+  type Output = Polynomial;
+
+  fn add(self, rhs: Self) -> Self::Output {
+    let len = self.coefficients.len().max(rhs.coefficients.len());
+    Polynomial::new((0..len).map(|p| &self.coefficient(p) + &rhs.coefficient(p)).collect())
+  }
+}
+
+impl ops::Sub for &Polynomial {
+  type Output = Polynomial;
+
+  fn sub(self, rhs: Self) -> Self::Output {
+    let len = self.coefficients.len().max(rhs.coefficients.len());
+    Polynomial::new((0..len).map(|p| &self.coefficient(p) - &rhs.coefficient(p)).collect())
+  }
+}
+
+impl ops::Mul for &Polynomial {
+  type Output = Polynomial;
+
+  fn mul(self, rhs: Self) -> Self::Output {
+    if self.degree().is_none() || rhs.degree().is_none() {
+      return Polynomial::new(Vec::new());
+    }
+    let mut coefficients = vec![Rational::from(0); self.coefficients.len() + rhs.coefficients.len() - 1];
+    for (i, l) in self.coefficients.iter().enumerate() {
+      for (j, r) in rhs.coefficients.iter().enumerate() {
+        coefficients[i + j] = &coefficients[i + j] + &(l * r);
+      }
+    }
+    Polynomial::new(coefficients)
+  }
+}
+
+/// The two sides of the root compared as polynomials in one of the leaves
+#[derive(Debug, Eq, PartialEq)]
+pub struct Equation {
+  unknown: String,
+  left: Polynomial,
+  right: Polynomial,
+}
+
+impl Equation {
+  /// Treat the root as an equality between its two sides with the named leaf as the unknown
+  pub fn new(root: &Node, unknown: &str) -> Result<Equation, SymbolicError> {
+    if !root.path_to(unknown, &mut Vec::new()) {
+      return Err(SymbolicError::UnknownVariable(unknown.to_string()));
+    }
+    match root {
+      Op(_, l, r) => Ok(Equation {
+        unknown: unknown.to_string(),
+        left: l.to_polynomial(unknown)?,
+        right: r.to_polynomial(unknown)?,
+      }),
+      Num(_, _) => Err(SymbolicError::NotAnEquation),
+    }
+  }
+
+  /// The same equation with every term in the unknown on the left and the constant on the right
+  pub fn simplify(&self) -> Equation {
+    let difference = &self.left - &self.right;
+    let constant = Polynomial::constant(difference.coefficient(0));
+    Equation {
+      unknown: self.unknown.clone(),
+      left: &difference - &constant,
+      right: Polynomial::constant(-&difference.coefficient(0)),
+    }
+  }
+
+  pub fn solve(&self) -> Result<Solution, SymbolicError> {
+    let difference = &self.left - &self.right;
+    match difference.degree() {
+      None => Ok(Solution::Infinite),
+      Some(0) => Ok(Solution::None),
+      Some(1) => Ok(Solution::Unique(&-&difference.coefficient(0) / &difference.coefficient(1))),
+      Some(degree) => Err(SymbolicError::NonLinear(degree)),
+    }
+  }
+}
+
+impl Display for Equation {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    self.left.write(f, &self.unknown)?;
+    write!(f, " = ")?;
+    self.right.write(f, &self.unknown)
+  }
+}
+
+/// Solve the root's equality for the named leaf
+pub fn solve_for(root: &Node, unknown: &str) -> Result<Solution, SymbolicError> {
+  Equation::new(root, unknown)?.solve()
 }
 
 #[cfg(test)]
 mod tests {
-  use crate::day21::{generator, part1, part2};
+  use crate::day21::{Equation, generator, human_value, MathError, part1, part2, Rational, Solution, solve_for,
+                     SymbolicError};
   use crate::day21::Node::Op;
 
   fn input() -> String {
@@ -162,7 +538,7 @@ mod tests {
   fn test_generator() {
     let root = generator(input().as_str());
     match root {
-      Op(f, _, _) => assert_eq!(f.evaluate(3, 5), 3 + 5),
+      Op(f, _, _) => assert_eq!(Ok(Rational::from(3 + 5)), f.evaluate(&Rational::from(3), &Rational::from(5))),
       _ => panic!()
     }
   }
@@ -173,6 +549,12 @@ mod tests {
     assert_eq!(152, part1(&root));
   }
 
+  #[test]
+  #[should_panic(expected = "Expecting a number for humn to yell: 605/3 is not a whole number")]
+  fn test_part2_not_integer() {
+    part2(&generator(input().replace("ljgn: 2", "ljgn: 3").as_str()));
+  }
+
   #[test]
   fn test_part2() {
     let mut result: Vec<char> = Vec::new();
@@ -181,4 +563,77 @@ mod tests {
     assert_eq!(vec!['<', '>', '>', '<', '<'], result);
     assert_eq!(301, part2(&root));
   }
-}
\ No newline at end of file
+
+  #[test]
+  fn test_exact() {
+    // Halves add up to a whole number even though each division is not exact
+    let root = generator("root: aaaa + aaaa\naaaa: cccc / dddd\ncccc: 1\ndddd: 2");
+    assert_eq!(1, part1(&root));
+
+    // ptdq has to be 596/3 here, so there is no whole number for humn to yell
+    let input = input().replace("ljgn: 2", "ljgn: 3");
+    let root = generator(input.as_str());
+    assert_eq!(Err(MathError::NotInteger(Rational::new(605, 3))), human_value(&root));
+
+    let root = generator(input.replace("sllz: 4", "sllz: 6").as_str());
+    assert_eq!(Ok(201), human_value(&root));
+
+    let root = generator("root: aaaa + bbbb\naaaa: humn * zero\nbbbb: 2\nhumn: 1\nzero: 0");
+    assert_eq!(Err(MathError::DivisionByZero), human_value(&root));
+  }
+
+  #[test]
+  fn test_rational() {
+    assert_eq!(Rational::new(1, 2), Rational::new(-3, -6));
+    assert_eq!(Rational::new(-1, 2), Rational::new(3, -6));
+    assert_eq!(Rational::new(5, 6), &Rational::new(1, 2) + &Rational::new(1, 3));
+    assert_eq!(Rational::new(1, 6), &Rational::new(1, 2) - &Rational::new(1, 3));
+    assert_eq!(Rational::from(3), &Rational::new(3, 2) / &Rational::new(1, 2));
+    assert_eq!(Err(MathError::DivisionByZero), Rational::from(3).checked_div(&Rational::from(0)));
+    assert_eq!(Ok(-4), Rational::new(8, -2).to_integer());
+    assert_eq!(Err(MathError::NotInteger(Rational::new(1, 2))), Rational::new(1, 2).to_integer());
+    let big = &Rational::from(i64::MAX) * &Rational::from(4);
+    assert_eq!(Err(MathError::OutOfRange(big.clone())), big.to_integer());
+    assert_eq!(Ok(i64::MAX), (&big / &Rational::from(4)).to_integer());
+    assert!(Rational::new(1, 3) < Rational::new(1, 2));
+    assert_eq!("-7/2", Rational::new(14, -4).to_string());
+    assert_eq!("4", Rational::new(8, 2).to_string());
+  }
+
+  #[test]
+  fn test_equation() {
+    let root = generator(input().as_str());
+    let equation = Equation::new(&root, "humn").unwrap();
+    assert_eq!("1/2 * humn - 1/2 = 150", equation.to_string());
+    assert_eq!("1/2 * humn = 301/2", equation.simplify().to_string());
+    assert_eq!(Ok(Solution::Unique(Rational::from(301))), equation.solve());
+
+    // Any leaf can be solved for, even when the answer is not a whole number
+    let equation = Equation::new(&root, "hmdt").unwrap();
+    assert_eq!("2 = 5 * hmdt - 10", equation.to_string());
+    assert_eq!("-5 * hmdt = -12", equation.simplify().to_string());
+    assert_eq!(Ok(Solution::Unique(Rational::new(12, 5))), equation.solve());
+    assert_eq!(Err(SymbolicError::NotPolynomial), Equation::new(&root, "lfqf"));
+    assert_eq!(Ok(Solution::Unique(Rational::new(1, 15))), solve_for(&root, "dbpl"));
+    assert_eq!(Err(SymbolicError::UnknownVariable("abcd".to_string())), solve_for(&root, "abcd"));
+  }
+
+  #[test]
+  fn test_solutions() {
+    // Both sides cancel out whatever humn is
+    let root = generator("root: aaaa + bbbb\naaaa: humn - humn\nbbbb: 0\nhumn: 1");
+    assert_eq!(Ok(Solution::Infinite), solve_for(&root, "humn"));
+    let root = generator("root: aaaa + bbbb\naaaa: humn - humn\nbbbb: 2\nhumn: 1");
+    assert_eq!(Ok(Solution::None), solve_for(&root, "humn"));
+    assert_eq!("0 = 2", Equation::new(&root, "humn").unwrap().simplify().to_string());
+    let root = generator("root: aaaa + bbbb\naaaa: humn * humn\nbbbb: 4\nhumn: 1");
+    assert_eq!("humn^2 = 4", Equation::new(&root, "humn").unwrap().to_string());
+    assert_eq!(Err(SymbolicError::NonLinear(2)), solve_for(&root, "humn"));
+    let root = generator("root: aaaa + bbbb\naaaa: cccc / humn\ncccc: 3\nbbbb: 4\nhumn: 1");
+    assert_eq!(Err(SymbolicError::NotPolynomial), solve_for(&root, "humn"));
+    let root = generator("root: aaaa + bbbb\naaaa: cccc / zero\ncccc: 3\nbbbb: humn - cccc\nzero: 0\nhumn: 1");
+    assert_eq!(Err(SymbolicError::DivisionByZero), solve_for(&root, "humn"));
+    let root = generator("root: 5");
+    assert_eq!(Err(SymbolicError::UnknownVariable("humn".to_string())), solve_for(&root, "humn"));
+  }
+}
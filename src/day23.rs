@@ -2,17 +2,12 @@ use std::collections::{HashMap, HashSet};
 use std::fmt::{Display, Formatter};
 use std::ops::RangeInclusive;
 
-const NORTH: u32 = 0;
-const SOUTH: u32 = 1;
-const WEST: u32 = 2;
-const EAST: u32 = 3;
-const DIRECTION_COUNT: usize = 4;
+use crate::day23::rules::{Rule, RuleSet};
+
+pub mod rules;
+
 const ELF_CHAR: char = '#';
 const EMPTY_CHAR: char = '.';
-const NORTH_BITS: u8 = 224;
-const SOUTH_BITS: u8 = 7;
-const WEST_BITS: u8 = 148;
-const EAST_BITS: u8 = 41;
 
 pub fn generator(input: &str) -> Elves {
   let mut layout: Vec<XY> = Vec::new();
@@ -29,13 +24,76 @@ pub fn generator(input: &str) -> Elves {
 }
 
 pub fn part1(elves: &Elves) -> usize {
-  let mut elves = elves.clone();
-  elves.perform_rounds(10);
-  elves.empty_space_count()
+  let mut grid = BitGrid::new(elves);
+  grid.perform_rounds(10);
+  grid.empty_space_count()
 }
 
 pub fn part2(elves: &Elves) -> usize {
-  elves.clone().run()
+  BitGrid::new(elves).run()
+}
+
+/// A way of storing the elves and moving them round by round
+pub trait Diffusion {
+  /// Perform a round following the rules, starting with the rule for the round number (counting
+  /// from 0), returning how many elves moved
+  fn perform_round_with(&mut self, rules: &RuleSet, round: usize) -> usize;
+
+  fn layout(&self) -> Vec<XY>;
+
+  fn elves(&self) -> Elves {
+    Elves { layout: self.layout() }
+  }
+
+  /// Perform a round with the puzzle rules, returning whether any elf moved
+  fn perform_round(&mut self, start: usize) -> bool {
+    self.perform_round_with(&RuleSet::puzzle(), start) > 0
+  }
+
+  fn perform_rounds(&mut self, rounds: usize) {
+    let rules = RuleSet::puzzle();
+    for i in 0..rounds {
+      if self.perform_round_with(&rules, i) == 0 {
+        break;
+      }
+    }
+  }
+
+  /// Perform rounds until the elves stop, returning the number of the first round where no elf
+  /// moved
+  fn run(&mut self) -> usize {
+    let rules = RuleSet::puzzle();
+    let mut round = 0;
+    while self.perform_round_with(&rules, round) > 0 {
+      round += 1;
+    }
+    round + 1
+  }
+
+  /// Perform up to `max_rounds` rounds following the rules, showing each to the observer, and
+  /// return the number of the first round where no elf moved if the elves stopped
+  fn observe(&mut self, rules: &RuleSet, max_rounds: usize, mut observer: impl FnMut(&Round)) -> Option<usize>
+    where Self: Sized {
+    for i in 0..max_rounds {
+      let moved = self.perform_round_with(rules, i);
+      let elves = self.elves();
+      let bounds = elves.bounds();
+      observer(&Round { number: i + 1, elves, bounds, moved });
+      if moved == 0 {
+        return Some(i + 1);
+      }
+    }
+    None
+  }
+}
+
+/// The elves at the end of a round, numbered from 1
+#[derive(Clone)]
+pub struct Round {
+  pub number: usize,
+  pub elves: Elves,
+  pub bounds: (RangeInclusive<i32>, RangeInclusive<i32>),
+  pub moved: usize,
 }
 
 #[derive(Clone)]
@@ -62,11 +120,12 @@ impl Display for Elves {
 }
 
 impl Elves {
-  fn empty_space_count(&self) -> usize {
+  pub fn empty_space_count(&self) -> usize {
     let (xb, yb) = self.bounds();
     (xb.count() * yb.count()) - self.layout.len()
   }
-  fn bounds(&self) -> (RangeInclusive<i32>, RangeInclusive<i32>) {
+
+  pub fn bounds(&self) -> (RangeInclusive<i32>, RangeInclusive<i32>) {
     let mut min_x = i32::MAX;
     let mut max_x = i32::MIN;
     let mut min_y = i32::MAX;
@@ -102,32 +161,28 @@ impl Elves {
     result
   }
 
-  fn propose(&self, elf: &XY, start: usize, positions: &HashSet<XY>) -> Option<XY> {
+  fn propose(&self, elf: &XY, rules: &RuleSet, round: usize, positions: &HashSet<XY>) -> Option<XY> {
     let neighbors = self.neighbors(elf, positions);
-    if neighbors == 0 {
+    if neighbors & rules.neighborhood() == 0 {
       return None;
     }
-    for i in 0..DIRECTION_COUNT {
-      match ((start + i) % DIRECTION_COUNT) as u32 {
-        NORTH if NORTH_BITS & neighbors == 0 => return Some(elf.add(0, -1)),
-        SOUTH if SOUTH_BITS & neighbors == 0 => return Some(elf.add(0, 1)),
-        WEST if WEST_BITS & neighbors == 0 => return Some(elf.add(-1, 0)),
-        EAST if EAST_BITS & neighbors == 0 => return Some(elf.add(1, 0)),
-        _ => {}
-      }
-    }
-    None
+    rules.rotation(round)
+      .find(|rule| rule.mask() & neighbors == 0)
+      .map(|rule| elf.add(rule.dx(), rule.dy()))
   }
+}
 
-  fn perform_round(&mut self,
-                   start: usize) -> bool {
+impl Diffusion for Elves {
+  fn perform_round_with(&mut self,
+                        rules: &RuleSet,
+                        round: usize) -> usize {
     let l = self.layout.len();
     let mut elf_next = Vec::with_capacity(l);
     let mut proposals = HashMap::with_capacity(l);
     let positions: HashSet<XY> = self.layout.clone().into_iter().collect();
 
     for elf in &self.layout {
-      let proposal = self.propose(elf, start, &positions);
+      let proposal = self.propose(elf, rules, round, &positions);
       match &proposal {
         Some(p) => {
           // Add the proposal
@@ -141,12 +196,12 @@ impl Elves {
     }
 
     // Record new positions where possible
-    let mut result = false;
+    let mut result = 0;
     for e in self.layout.iter_mut().rev() {
       match elf_next.pop().unwrap() {
         Some(p) if proposals.get(&p).unwrap() == &1 => {
           *e = p;
-          result = true;
+          result += 1;
         }
         _ => {}
       }
@@ -154,20 +209,366 @@ impl Elves {
     result
   }
 
-  fn perform_rounds(&mut self, rounds: usize) {
-    for i in 0..rounds {
-      if !self.perform_round(i) {
-        break;
+  fn layout(&self) -> Vec<XY> {
+    self.layout.clone()
+  }
+}
+
+/// Extra rows added above and below whenever the elves reach the edge of a [BitGrid]
+const GROWTH_ROWS: usize = 8;
+
+/// The elves as rows of bits, bit `i` of a row being the column `i` places right of the origin,
+/// so that a round works on 64 columns at a time with shifts and masks. The outer rows and
+/// columns are kept empty before each round so that no elf can step off the grid.
+#[derive(Clone, Debug)]
+pub struct BitGrid {
+  /// Words per row
+  words: usize,
+  height: usize,
+  /// The position of bit 0 of the first row
+  origin: XY,
+  cells: Vec<u64>,
+  scratch: Scratch,
+}
+
+/// Buffers for a round of a [BitGrid], kept between rounds to save reallocating them
+#[derive(Clone, Debug, Default)]
+struct Scratch {
+  /// The number of rules and the size of the grid the buffers are for
+  shape: (usize, usize),
+  /// The elves proposing to move by each rule, one grid after another
+  proposals: Vec<u64>,
+  /// Cells which more than one elf proposes to move to
+  blocked: Vec<u64>,
+  /// Cells which exactly one elf proposes to move to
+  arrived: Vec<u64>,
+}
+
+impl Scratch {
+  /// Size the buffers for the rules and grid. Every round overwrites all but the outer rows of
+  /// the proposals, which stay empty, so they only need clearing when the shape changes.
+  fn reset(&mut self, rules: usize, size: usize) {
+    if self.shape == (rules, size) {
+      return;
+    }
+    self.shape = (rules, size);
+    for (buffer, len) in [(&mut self.proposals, rules * size), (&mut self.blocked, size), (&mut self.arrived, size)] {
+      buffer.clear();
+      buffer.resize(len, 0);
+    }
+  }
+}
+
+/// Each bit of word `w` of the row set to the bit to its west, i.e. the row shifted east
+fn from_west(row: &[u64], w: usize) -> u64 {
+  (row[w] << 1) | if w > 0 { row[w - 1] >> 63 } else { 0 }
+}
+
+/// Each bit of word `w` of the row set to the bit to its east, i.e. the row shifted west
+fn from_east(row: &[u64], w: usize) -> u64 {
+  (row[w] >> 1) | if w + 1 < row.len() { row[w + 1] << 63 } else { 0 }
+}
+
+/// Each bit of word `w` of the row set to the bit `dx` columns to its west
+fn shifted(row: &[u64], w: usize, dx: i32) -> u64 {
+  match dx {
+    1 => from_west(row, w),
+    -1 => from_east(row, w),
+    _ => row[w],
+  }
+}
+
+/// The neighbors of the bits of word `w` of a row, given the rows either side, indexed by the
+/// neighbor bits of [rules::neighbor_bit]
+fn neighbors(north: &[u64], here: &[u64], south: &[u64], w: usize) -> [u64; 8] {
+  [
+    from_east(south, w), south[w], from_west(south, w),
+    from_east(here, w), from_west(here, w),
+    from_east(north, w), north[w], from_west(north, w),
+  ]
+}
+
+/// Or together the neighbors in the mask
+fn occupied(neighbors: &[u64; 8], mut mask: u8) -> u64 {
+  let mut occupied = 0;
+  while mask != 0 {
+    occupied |= neighbors[mask.trailing_zeros() as usize];
+    mask &= mask - 1;
+  }
+  occupied
+}
+
+/// The elves proposing to move north, south, west and east in turn, one grid after another
+fn puzzle_proposals(cells: &[u64], words: usize, start: usize, proposals: &mut [u64]) {
+  let size = cells.len();
+  for y in 1..size / words - 1 {
+    let (north, here, south) = (&cells[(y - 1) * words..y * words], &cells[y * words..(y + 1) * words],
+                                &cells[(y + 1) * words..(y + 2) * words]);
+    for w in 0..words {
+      let [se, s, sw, east, west, ne, n, nw] = neighbors(north, here, south, w);
+      let free = [!(nw | n | ne), !(sw | s | se), !(nw | west | sw), !(ne | east | se)];
+      let alone = free[0] & free[1] & free[2] & free[3];
+      let mut remaining = here[w] & !alone;
+      for k in 0..4 {
+        let dir = (start + k) % 4;
+        let proposal = remaining & free[dir];
+        proposals[dir * size + y * words + w] = proposal;
+        remaining &= !proposal;
       }
     }
   }
+}
 
-  fn run(&mut self) -> usize {
-    let mut round = 0;
-    while self.perform_round(round) {
-      round += 1;
+/// The elves proposing to move by each rule, in the order they're tried, one grid after another
+fn rule_proposals(cells: &[u64], words: usize, order: &[&Rule], neighborhood: u8, proposals: &mut [u64]) {
+  let size = cells.len();
+  for y in 1..size / words - 1 {
+    let (north, here, south) = (&cells[(y - 1) * words..y * words], &cells[y * words..(y + 1) * words],
+                                &cells[(y + 1) * words..(y + 2) * words]);
+    for w in 0..words {
+      let neighbors = neighbors(north, here, south, w);
+      let mut remaining = here[w] & occupied(&neighbors, neighborhood);
+      for (r, rule) in order.iter().enumerate() {
+        let proposal = remaining & !occupied(&neighbors, rule.mask());
+        proposals[r * size + y * words + w] = proposal;
+        remaining &= !proposal;
+      }
+    }
+  }
+}
+
+/// The cells more than one elf proposes to move to, and those exactly one elf does, for the
+/// proposals of [puzzle_proposals]
+fn puzzle_clashes(proposals: &[u64], words: usize, blocked: &mut [u64], arrived: &mut [u64]) {
+  let size = blocked.len();
+  let height = size / words;
+  let (to_north, rest) = proposals.split_at(size);
+  let (to_south, rest) = rest.split_at(size);
+  let (to_west, to_east) = rest.split_at(size);
+  for y in 0..height {
+    let (west_row, east_row) = (&to_west[y * words..(y + 1) * words], &to_east[y * words..(y + 1) * words]);
+    for w in 0..words {
+      let from_south = if y + 1 < height { to_north[(y + 1) * words + w] } else { 0 };
+      let from_north = if y > 0 { to_south[(y - 1) * words + w] } else { 0 };
+      let from_right = from_east(west_row, w);
+      let from_left = from_west(east_row, w);
+      let vertical = from_south | from_north;
+      let horizontal = from_right | from_left;
+      let clash = (from_south & from_north) | (vertical & horizontal) | (from_right & from_left);
+      blocked[y * words + w] = clash;
+      arrived[y * words + w] = (vertical | horizontal) & !clash;
+    }
+  }
+}
+
+/// The cells more than one elf proposes to move to, and those exactly one elf does, for the
+/// proposals of [rule_proposals] with the steps of their rules
+fn rule_clashes(proposals: &[u64], words: usize, steps: &[(i32, i32)], blocked: &mut [u64], arrived: &mut [u64]) {
+  let size = blocked.len();
+  let height = size / words;
+  for y in 0..height {
+    for w in 0..words {
+      let mut once = 0;
+      let mut twice = 0;
+      for (r, (dx, dy)) in steps.iter().enumerate() {
+        let from = y as i32 - dy;
+        if from < 0 || from >= height as i32 {
+          continue;
+        }
+        let start = r * size + from as usize * words;
+        let arriving = shifted(&proposals[start..start + words], w, *dx);
+        twice |= once & arriving;
+        once |= arriving;
+      }
+      blocked[y * words + w] = twice;
+      arrived[y * words + w] = once & !twice;
+    }
+  }
+}
+
+/// Move the elves of [puzzle_proposals] whose proposed cells aren't blocked, returning how many
+/// moved
+fn puzzle_moves(proposals: &[u64], words: usize, blocked: &[u64], arrived: &[u64], cells: &mut [u64]) -> usize {
+  let size = cells.len();
+  let (to_north, rest) = proposals.split_at(size);
+  let (to_south, rest) = rest.split_at(size);
+  let (to_west, to_east) = rest.split_at(size);
+  let mut moved = 0;
+  for y in 1..size / words - 1 {
+    let blocked_row = &blocked[y * words..(y + 1) * words];
+    for w in 0..words {
+      let i = y * words + w;
+      let left = (to_north[i] & !blocked[i - words])
+        | (to_south[i] & !blocked[i + words])
+        | (to_west[i] & !from_west(blocked_row, w))
+        | (to_east[i] & !from_east(blocked_row, w));
+      moved += left.count_ones() as usize;
+      cells[i] &= !left;
     }
-    round + 1
+  }
+  for (cell, arrival) in cells.iter_mut().zip(arrived) {
+    *cell |= arrival;
+  }
+  moved
+}
+
+/// Move the elves of [rule_proposals] whose proposed cells aren't blocked, returning how many
+/// moved
+fn rule_moves(proposals: &[u64], words: usize, steps: &[(i32, i32)], blocked: &[u64], arrived: &[u64],
+              cells: &mut [u64]) -> usize {
+  let size = cells.len();
+  let mut moved = 0;
+  for y in 1..size / words - 1 {
+    for w in 0..words {
+      let i = y * words + w;
+      let mut left = 0;
+      for (r, (dx, dy)) in steps.iter().enumerate() {
+        let to = (y as i32 + dy) as usize;
+        left |= proposals[r * size + i] & !shifted(&blocked[to * words..(to + 1) * words], w, -dx);
+      }
+      moved += left.count_ones() as usize;
+      cells[i] &= !left;
+    }
+  }
+  for (cell, arrival) in cells.iter_mut().zip(arrived) {
+    *cell |= arrival;
+  }
+  moved
+}
+
+impl BitGrid {
+  pub fn new(elves: &Elves) -> BitGrid {
+    let (x_range, y_range) = elves.bounds();
+    let words = (x_range.clone().count() + 2).div_ceil(64);
+    let height = y_range.clone().count() + 2;
+    let origin = XY::new(x_range.start() - 1, y_range.start() - 1);
+    let mut grid = BitGrid { words, height, origin, cells: vec![0; words * height], scratch: Scratch::default() };
+    for elf in &elves.layout {
+      grid.set(elf);
+    }
+    grid
+  }
+
+  fn set(&mut self, elf: &XY) {
+    let x = (elf.x - self.origin.x) as usize;
+    let y = (elf.y - self.origin.y) as usize;
+    self.cells[y * self.words + x / 64] |= 1 << (x % 64);
+  }
+
+  fn row(&self, y: usize) -> &[u64] {
+    &self.cells[y * self.words..(y + 1) * self.words]
+  }
+
+  pub fn len(&self) -> usize {
+    self.cells.iter().map(|w| w.count_ones() as usize).sum()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.cells.iter().all(|w| *w == 0)
+  }
+
+  /// The columns and rows enclosing all the elves
+  pub fn bounds(&self) -> (RangeInclusive<i32>, RangeInclusive<i32>) {
+    let mut min_x = i32::MAX;
+    let mut max_x = i32::MIN;
+    let mut min_y = i32::MAX;
+    let mut max_y = i32::MIN;
+
+    for y in 0..self.height {
+      for (w, word) in self.row(y).iter().enumerate().filter(|(_, word)| **word != 0) {
+        min_x = min_x.min((w * 64) as i32 + word.trailing_zeros() as i32);
+        max_x = max_x.max((w * 64 + 63) as i32 - word.leading_zeros() as i32);
+        min_y = min_y.min(y as i32);
+        max_y = max_y.max(y as i32);
+      }
+    }
+    if min_y > max_y {
+      return (min_x..=max_x, min_y..=max_y);
+    }
+    (self.origin.x + min_x..=self.origin.x + max_x, self.origin.y + min_y..=self.origin.y + max_y)
+  }
+
+  pub fn empty_space_count(&self) -> usize {
+    let (xb, yb) = self.bounds();
+    (xb.count() * yb.count()) - self.len()
+  }
+
+  /// Add empty rows or words of columns on any side the elves have reached
+  fn ensure_margin(&mut self) {
+    let top = self.row(0).iter().any(|w| *w != 0);
+    let bottom = self.row(self.height - 1).iter().any(|w| *w != 0);
+    let left = (0..self.height).any(|y| self.row(y)[0] & 1 != 0);
+    let right = (0..self.height).any(|y| self.row(y)[self.words - 1] >> 63 != 0);
+    if !(top || bottom || left || right) {
+      return;
+    }
+    let (rows_above, rows_below) = (if top { GROWTH_ROWS } else { 0 }, if bottom { GROWTH_ROWS } else { 0 });
+    let (words_left, words_right) = (left as usize, right as usize);
+    let words = self.words + words_left + words_right;
+    let height = self.height + rows_above + rows_below;
+    let mut cells = vec![0; words * height];
+    for y in 0..self.height {
+      let start = (y + rows_above) * words + words_left;
+      cells[start..start + self.words].copy_from_slice(self.row(y));
+    }
+    self.origin = self.origin.add(-64 * words_left as i32, -(rows_above as i32));
+    self.words = words;
+    self.height = height;
+    self.cells = cells;
+  }
+
+  /// A round of the puzzle rules, with the four directions worked out together
+  fn puzzle_round(&mut self, start: usize) -> usize {
+    let (words, size) = (self.words, self.cells.len());
+    self.scratch.reset(4, size);
+    let Scratch { proposals, blocked, arrived, .. } = &mut self.scratch;
+    let cells = &mut self.cells;
+
+    puzzle_proposals(cells, words, start, proposals);
+
+    puzzle_clashes(proposals, words, blocked, arrived);
+    puzzle_moves(proposals, words, blocked, arrived, cells)
+  }
+}
+
+impl Diffusion for BitGrid {
+  fn perform_round_with(&mut self, rules: &RuleSet, round: usize) -> usize {
+    self.ensure_margin();
+    if rules.is_puzzle() {
+      return self.puzzle_round(round);
+    }
+    let (words, size) = (self.words, self.cells.len());
+    let order: Vec<&Rule> = rules.rotation(round).collect();
+    self.scratch.reset(order.len(), size);
+    let Scratch { proposals, blocked, arrived, .. } = &mut self.scratch;
+    let cells = &mut self.cells;
+
+    rule_proposals(cells, words, &order, rules.neighborhood(), proposals);
+
+    let steps: Vec<(i32, i32)> = order.iter().map(|rule| (rule.dx(), rule.dy())).collect();
+    rule_clashes(proposals, words, &steps, blocked, arrived);
+    rule_moves(proposals, words, &steps, blocked, arrived, cells)
+  }
+
+  fn layout(&self) -> Vec<XY> {
+    let mut layout = Vec::with_capacity(self.len());
+    for y in 0..self.height {
+      for (w, word) in self.row(y).iter().enumerate() {
+        let mut bits = *word;
+        while bits != 0 {
+          let x = w * 64 + bits.trailing_zeros() as usize;
+          layout.push(self.origin.add(x as i32, y as i32));
+          bits &= bits - 1;
+        }
+      }
+    }
+    layout
+  }
+}
+
+impl Display for BitGrid {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    write!(f, "{}", self.elves())
   }
 }
 
@@ -189,7 +590,8 @@ impl XY {
 
 #[cfg(test)]
 mod tests {
-  use crate::day23::{generator, XY};
+  use crate::day23::{BitGrid, Diffusion, Elves, generator, part1, part2, XY};
+  use crate::day23::rules::{Rule, RuleSet};
 
   fn input() -> String {
     vec![
@@ -355,4 +757,103 @@ mod tests {
     ].join("\n");
     assert_eq!(exp, elves.to_string());
   }
-}
\ No newline at end of file
+
+  #[test]
+  fn test_backends_agree() {
+    let elves = generator(input().as_str());
+    let mut grid = BitGrid::new(&elves);
+    let mut reference = elves.clone();
+    assert_eq!(elves.to_string(), grid.to_string());
+    for round in 0..25 {
+      assert_eq!(reference.perform_round(round), grid.perform_round(round));
+      assert_eq!(reference.to_string(), grid.to_string());
+      assert_eq!(reference.bounds(), grid.bounds());
+    }
+    assert_eq!(110, part1(&elves));
+    assert_eq!(20, part2(&elves));
+  }
+
+  #[test]
+  fn test_bit_grid_growth() {
+    // A wide row of elves spreads past the word boundaries and the top and bottom of the grid
+    let elves = generator(["#".repeat(150).as_str(), "#".repeat(150).as_str()].join("\n").as_str());
+    let mut grid = BitGrid::new(&elves);
+    let mut reference = elves.clone();
+    for round in 0..40 {
+      assert_eq!(reference.perform_round(round), grid.perform_round(round));
+      let mut expected = reference.layout.clone();
+      let mut actual = grid.layout();
+      expected.sort_by_key(|xy| (xy.y, xy.x));
+      actual.sort_by_key(|xy| (xy.y, xy.x));
+      assert_eq!(expected, actual);
+    }
+    assert_eq!(300, grid.len());
+    assert_eq!(reference.empty_space_count(), grid.empty_space_count());
+  }
+
+  fn sorted(mut layout: Vec<XY>) -> Vec<XY> {
+    layout.sort_by_key(|xy| (xy.y, xy.x));
+    layout
+  }
+
+  #[test]
+  fn test_rule_sets_agree() {
+    let elves = generator(input().as_str());
+    for rules in [RuleSet::puzzle(), RuleSet::eight_directions(), RuleSet::hexagonal()] {
+      let mut grid = BitGrid::new(&elves);
+      let mut reference = elves.clone();
+      for round in 0..30 {
+        assert_eq!(reference.perform_round_with(&rules, round), grid.perform_round_with(&rules, round));
+        assert_eq!(sorted(reference.layout()), sorted(grid.layout()));
+      }
+    }
+  }
+
+  #[test]
+  fn test_switching_rules() {
+    // The buffers a grid keeps between rounds are resized as the rules and grid change
+    let elves = generator(input().as_str());
+    let rule_sets = [RuleSet::puzzle(), RuleSet::eight_directions(), RuleSet::hexagonal()];
+    let mut grid = BitGrid::new(&elves);
+    let mut reference = elves.clone();
+    for round in 0..40 {
+      let rules = &rule_sets[round / 3 % rule_sets.len()];
+      assert_eq!(reference.perform_round_with(rules, round), grid.perform_round_with(rules, round));
+      assert_eq!(sorted(reference.layout()), sorted(grid.layout()));
+    }
+  }
+
+  #[test]
+  fn test_custom_order() {
+    // Starting with south is the same as the puzzle rules from the second round
+    let rules = RuleSet::new(vec![Rule::facing(0, 1), Rule::facing(-1, 0), Rule::facing(1, 0), Rule::facing(0, -1)]);
+    let elves = generator(input().as_str());
+    let mut custom = BitGrid::new(&elves);
+    let mut puzzle = BitGrid::new(&elves);
+    for round in 0..10 {
+      custom.perform_round_with(&rules, round);
+      puzzle.perform_round(round + 1);
+    }
+    assert_eq!(puzzle.to_string(), custom.to_string());
+  }
+
+  #[test]
+  fn test_observe() {
+    let elves = generator(input().as_str());
+    let mut rounds: Vec<(usize, usize, usize)> = Vec::new();
+    let stopped = BitGrid::new(&elves).observe(&RuleSet::puzzle(), 100, |round| {
+      let (xb, yb) = round.bounds.clone();
+      assert_eq!(round.elves.bounds(), round.bounds);
+      rounds.push((round.number, round.moved, xb.count() * yb.count()));
+    });
+    assert_eq!(Some(20), stopped);
+    assert_eq!(20, rounds.len());
+    assert_eq!((20, 0), (rounds[19].0, rounds[19].1));
+    assert_eq!(110 + 22, rounds[9].2);
+
+    let mut moved = Vec::new();
+    let mut simple: Elves = generator(["##", "#.", "..", "##"].join("\n").as_str());
+    assert_eq!(None, simple.observe(&RuleSet::puzzle(), 2, |round| moved.push(round.moved)));
+    assert_eq!(vec![3, 5], moved);
+  }
+}
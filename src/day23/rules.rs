@@ -0,0 +1,202 @@
+/// The neighbors which must be empty for an elf to move in each of the puzzle directions, using
+/// the bits of [neighbor_bit]
+const NORTH_BITS: u8 = 224;
+const SOUTH_BITS: u8 = 7;
+const WEST_BITS: u8 = 148;
+const EAST_BITS: u8 = 41;
+const ALL_NEIGHBORS: u8 = 255;
+
+const PUZZLE_RULES: [Rule; 4] = [
+  Rule { dx: 0, dy: -1, mask: NORTH_BITS },
+  Rule { dx: 0, dy: 1, mask: SOUTH_BITS },
+  Rule { dx: -1, dy: 0, mask: WEST_BITS },
+  Rule { dx: 1, dy: 0, mask: EAST_BITS },
+];
+
+/// The six neighbors of a cell on a hexagonal grid in axial coordinates, in order around the ring
+const HEX_RING: [(i32, i32); 6] = [(1, 0), (1, -1), (0, -1), (-1, 0), (-1, 1), (0, 1)];
+
+/// The bit for the neighbor at the given offset, the bit positions being as given below with x
+/// the elf itself
+///
+/// ```text
+/// 765
+/// 4x3
+/// 210
+/// ```
+pub fn neighbor_bit(dx: i32, dy: i32) -> u8 {
+  assert!(dx.abs() <= 1 && dy.abs() <= 1 && (dx, dy) != (0, 0), "({dx}, {dy}) is not a neighbor");
+  let index = (dy + 1) * 3 + dx + 1;
+  let index = if index > 4 { index - 1 } else { index };
+  1 << (7 - index)
+}
+
+/// The offsets of the neighbors in the mask
+pub fn neighbors_in(mask: u8) -> impl Iterator<Item=(i32, i32)> {
+  (-1..=1).flat_map(|dy| (-1..=1).map(move |dx| (dx, dy)))
+    .filter(move |&(dx, dy)| (dx, dy) != (0, 0) && mask & neighbor_bit(dx, dy) != 0)
+}
+
+/// A step an elf proposes when none of the neighbors in the mask are occupied
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Rule {
+  dx: i32,
+  dy: i32,
+  mask: u8,
+}
+
+impl Rule {
+  /// The mask must include the cell stepped into, so that no elf moves onto one staying put
+  pub fn new(dx: i32, dy: i32, mask: u8) -> Rule {
+    assert!(mask & neighbor_bit(dx, dy) != 0, "The mask for a step of ({dx}, {dy}) must include the cell stepped into");
+    Rule { dx, dy, mask }
+  }
+
+  /// A rule stepping to the given neighbor when it and the neighbors either side of it are empty
+  pub fn facing(dx: i32, dy: i32) -> Rule {
+    let mut mask = 0;
+    for y in -1..=1 {
+      for x in -1..=1 {
+        let near = (x - dx).abs() <= 1 && (y - dy).abs() <= 1;
+        if (x, y) != (0, 0) && near && x * dx + y * dy > 0 {
+          mask |= neighbor_bit(x, y);
+        }
+      }
+    }
+    Rule::new(dx, dy, mask)
+  }
+
+  pub fn dx(&self) -> i32 {
+    self.dx
+  }
+
+  pub fn dy(&self) -> i32 {
+    self.dy
+  }
+
+  pub fn mask(&self) -> u8 {
+    self.mask
+  }
+}
+
+/// The rules the elves try in turn, the first rule tried moving on by one each round, and the
+/// neighbors an elf must have before it wants to move at all
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct RuleSet {
+  rules: Vec<Rule>,
+  neighborhood: u8,
+}
+
+impl Default for RuleSet {
+  fn default() -> Self {
+    RuleSet::puzzle()
+  }
+}
+
+impl RuleSet {
+  pub fn new(rules: Vec<Rule>) -> RuleSet {
+    assert!(!rules.is_empty(), "A rule set needs at least one rule");
+    RuleSet { rules, neighborhood: ALL_NEIGHBORS }
+  }
+
+  /// Only count the neighbors in the mask when deciding whether an elf is alone
+  pub fn with_neighborhood(mut self, neighborhood: u8) -> RuleSet {
+    self.neighborhood = neighborhood;
+    self
+  }
+
+  /// North, south, west then east
+  pub fn puzzle() -> RuleSet {
+    RuleSet::new(PUZZLE_RULES.to_vec())
+  }
+
+  /// Whether these are the rules of [RuleSet::puzzle], which can be worked out more quickly
+  pub fn is_puzzle(&self) -> bool {
+    self.rules == PUZZLE_RULES && self.neighborhood == ALL_NEIGHBORS
+  }
+
+  /// The puzzle directions followed by the diagonals, north west, north east, south west then
+  /// south east
+  pub fn eight_directions() -> RuleSet {
+    let mut rules = RuleSet::puzzle().rules;
+    rules.extend([(-1, -1), (1, -1), (-1, 1), (1, 1)].map(|(dx, dy)| Rule::facing(dx, dy)));
+    RuleSet::new(rules)
+  }
+
+  /// Elves on a hexagonal grid in axial coordinates, where the north west and south east cells
+  /// aren't neighbors. Each rule steps to a neighbor when it and the two either side of it around
+  /// the ring are empty.
+  pub fn hexagonal() -> RuleSet {
+    let rules = [2, 5, 3, 0, 1, 4].map(|i| {
+      let (dx, dy) = HEX_RING[i];
+      let mask = [5, 0, 1].iter()
+        .map(|offset| HEX_RING[(i + offset) % HEX_RING.len()])
+        .fold(0, |mask, (x, y)| mask | neighbor_bit(x, y));
+      Rule::new(dx, dy, mask)
+    });
+    let neighborhood = HEX_RING.iter().fold(0, |mask, (x, y)| mask | neighbor_bit(*x, *y));
+    RuleSet::new(rules.to_vec()).with_neighborhood(neighborhood)
+  }
+
+  pub fn rules(&self) -> &[Rule] {
+    &self.rules
+  }
+
+  pub fn neighborhood(&self) -> u8 {
+    self.neighborhood
+  }
+
+  /// The rules in the order they're tried in the given round, counting from 0
+  pub fn rotation(&self, round: usize) -> impl Iterator<Item=&Rule> {
+    let start = round % self.rules.len();
+    self.rules[start..].iter().chain(&self.rules[..start])
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::day23::rules::{EAST_BITS, neighbor_bit, neighbors_in, NORTH_BITS, Rule, RuleSet, SOUTH_BITS, WEST_BITS};
+
+  #[test]
+  fn test_masks() {
+    assert_eq!(128, neighbor_bit(-1, -1));
+    assert_eq!(8, neighbor_bit(1, 0));
+    assert_eq!(1, neighbor_bit(1, 1));
+    assert_eq!(NORTH_BITS, Rule::facing(0, -1).mask());
+    assert_eq!(SOUTH_BITS, Rule::facing(0, 1).mask());
+    assert_eq!(WEST_BITS, Rule::facing(-1, 0).mask());
+    assert_eq!(EAST_BITS, Rule::facing(1, 0).mask());
+    assert_eq!(neighbor_bit(1, -1) | neighbor_bit(0, -1) | neighbor_bit(1, 0), Rule::facing(1, -1).mask());
+    assert_eq!(vec![(-1, -1), (0, -1), (1, -1)], neighbors_in(NORTH_BITS).collect::<Vec<_>>());
+    assert_eq!(8, neighbors_in(255).count());
+  }
+
+  #[test]
+  fn test_rule_sets() {
+    let rules = RuleSet::puzzle();
+    let order: Vec<(i32, i32)> = rules.rotation(5).map(|r| (r.dx(), r.dy())).collect();
+    assert_eq!(vec![(0, 1), (-1, 0), (1, 0), (0, -1)], order);
+    assert_eq!(8, RuleSet::eight_directions().rules().len());
+    assert!(RuleSet::puzzle().is_puzzle());
+    assert!(!RuleSet::new(RuleSet::puzzle().rotation(1).cloned().collect()).is_puzzle());
+    assert!(!RuleSet::puzzle().with_neighborhood(NORTH_BITS).is_puzzle());
+
+    let hex = RuleSet::hexagonal();
+    assert_eq!(126, hex.neighborhood());
+    assert_eq!(6, hex.rules().len());
+    assert_eq!(neighbor_bit(0, -1) | neighbor_bit(1, -1) | neighbor_bit(-1, 0), hex.rules()[0].mask());
+    assert!(hex.rules().iter().all(|r| r.mask() & !hex.neighborhood() == 0));
+  }
+
+  #[test]
+  #[should_panic(expected = "is not a neighbor")]
+  fn test_not_a_neighbor() {
+    Rule::new(2, 0, 0);
+  }
+
+  #[test]
+  #[should_panic(expected = "must include the cell stepped into")]
+  fn test_mask_without_target() {
+    RuleSet::new(vec![Rule::new(1, 0, 0)]).with_neighborhood(neighbor_bit(1, 0));
+  }
+}
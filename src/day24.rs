@@ -7,6 +7,8 @@ const DOWN_WIND: char = 'v';
 const LEFT_WIND: char = '<';
 const RIGHT_WIND: char = '>';
 const CLEAR: char = '.';
+/// Waiting, then the moves the expedition can make each minute
+const STEPS: [(i32, i32); 5] = [(0, 0), (1, 0), (-1, 0), (0, -1), (0, 1)];
 
 pub fn generator(input: &str) -> Valley {
   let map: Vec<Vec<char>> = input.lines().map(|l| l.chars().collect()).collect();
@@ -35,27 +37,27 @@ pub fn generator(input: &str) -> Valley {
 }
 
 pub fn part1(valley: &Valley) -> i32 {
-  valley.find_time(&valley.start, &valley.end.add(0, -1), 0)
+  let waypoints = [valley.start.clone(), valley.end.clone()];
+  Solver::new(valley).trip(&waypoints, 0).expect("Did not find a path").end()
 }
 
 pub fn part2(valley: &Valley) -> i32 {
-  let mut t = part1(valley);
-  t = valley.find_time(&valley.end, &valley.start.add(0, 1), t);
-  valley.find_time(&valley.start, &valley.end.add(0, -1), t)
+  let waypoints = [valley.start.clone(), valley.end.clone(), valley.start.clone(), valley.end.clone()];
+  Solver::new(valley).trip(&waypoints, 0).expect("Did not find a path").end()
 }
 
 #[derive(Clone, Debug, Eq, PartialEq, Hash)]
-struct XY {
-  x: i32,
-  y: i32,
+pub struct XY {
+  pub x: i32,
+  pub y: i32,
 }
 
 impl XY {
-  fn new(x: i32, y: i32) -> XY {
+  pub fn new(x: i32, y: i32) -> XY {
     XY { x, y }
   }
 
-  fn add(&self, x: i32, y: i32) -> XY {
+  pub fn add(&self, x: i32, y: i32) -> XY {
     XY::new(self.x + x, self.y + y)
   }
 }
@@ -69,6 +71,16 @@ pub struct Valley {
 }
 
 impl Valley {
+  /// The entrance in the top wall
+  pub fn start(&self) -> &XY {
+    &self.start
+  }
+
+  /// The exit in the bottom wall
+  pub fn end(&self) -> &XY {
+    &self.end
+  }
+
   fn space(&self, x: i32, y: i32) -> &char {
     &self.map[y as usize][x as usize]
   }
@@ -87,7 +99,9 @@ impl Valley {
     }
   }
 
-  fn find_time(&self, start: &XY, end: &XY, start_t: i32) -> i32 {
+  /// The minute the expedition reaches `end` after leaving `start` at `start_t`, searching every
+  /// position at every minute
+  pub fn find_time(&self, start: &XY, end: &XY, start_t: i32) -> i32 {
     let mut explored: HashSet<(XY, i32)> = HashSet::new();
     let mut queue = VecDeque::new();
     queue.push_back((start.clone(), start_t));
@@ -144,9 +158,297 @@ impl Valley {
   }
 }
 
+fn gcd(a: usize, b: usize) -> usize {
+  if b == 0 { a } else { gcd(b, a % b) }
+}
+
+/// Which cells of the valley are free of blizzards at each minute of the period after which the
+/// blizzards repeat, as rows of bits. Bit `x` of row `y` is the cell `(x + 1, y + 1)` of the map,
+/// leaving at least one unused bit at the end of each row.
+#[derive(Clone, Debug)]
+pub struct Blizzards {
+  width: usize,
+  height: usize,
+  words: usize,
+  period: usize,
+  free: Vec<Vec<u64>>,
+}
+
+impl Blizzards {
+  pub fn new(valley: &Valley) -> Blizzards {
+    let (width, height) = (valley.width as usize, valley.height as usize);
+    let period = width / gcd(width, height) * height;
+    let words = (width + 1).div_ceil(64);
+    let mut row = vec![0; words];
+    for x in 0..width {
+      row[x / 64] |= 1 << (x % 64);
+    }
+    let mut free = vec![row.repeat(height); period];
+    for y in 0..height {
+      for x in 0..width {
+        let (dx, dy) = match *valley.space(x as i32 + 1, y as i32 + 1) {
+          RIGHT_WIND => (1, 0),
+          LEFT_WIND => (-1, 0),
+          UP_WIND => (0, -1),
+          DOWN_WIND => (0, 1),
+          _ => continue,
+        };
+        for (t, cells) in free.iter_mut().enumerate() {
+          let bx = (x as i64 + dx * t as i64).rem_euclid(width as i64) as usize;
+          let by = (y as i64 + dy * t as i64).rem_euclid(height as i64) as usize;
+          cells[by * words + bx / 64] &= !(1 << (bx % 64));
+        }
+      }
+    }
+    Blizzards { width, height, words, period, free }
+  }
+
+  pub fn period(&self) -> usize {
+    self.period
+  }
+
+  /// The free cells of row `y` of the inside of the valley at the given minute
+  pub fn free_row(&self, y: usize, t: i32) -> &[u64] {
+    let cells = &self.free[t.rem_euclid(self.period as i32) as usize];
+    &cells[y * self.words..(y + 1) * self.words]
+  }
+
+  /// Whether the cell of the map is inside the valley and free at the given minute
+  pub fn is_free(&self, x: i32, y: i32, t: i32) -> bool {
+    if x <= 0 || x > self.width as i32 || y <= 0 || y > self.height as i32 {
+      return false;
+    }
+    let x = (x - 1) as usize;
+    self.free_row((y - 1) as usize, t)[x / 64] & (1 << (x % 64)) != 0
+  }
+}
+
+/// A trip through the valley, with where the expedition is at each minute from the start
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Route {
+  pub start: i32,
+  pub positions: Vec<XY>,
+  /// The minutes at which each waypoint after the first was reached
+  pub arrivals: Vec<i32>,
+}
+
+impl Route {
+  pub fn end(&self) -> i32 {
+    self.start + self.positions.len() as i32 - 1
+  }
+
+  /// Each minute of the trip as in the puzzle, e.g. `Minute 1, move down.`
+  pub fn describe(&self) -> String {
+    self.positions.windows(2).enumerate().map(|(i, step)| {
+      let action = match (step[1].x - step[0].x, step[1].y - step[0].y) {
+        (1, 0) => "move right",
+        (-1, 0) => "move left",
+        (0, -1) => "move up",
+        (0, 1) => "move down",
+        _ => "wait",
+      };
+      format!("Minute {}, {action}.", self.start + i as i32 + 1)
+    }).collect::<Vec<String>>().join("\n")
+  }
+}
+
+/// How a [Solver] searches for the quickest way between two cells
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Engine {
+  /// Search each cell at each minute of the blizzard period at most once
+  States,
+  /// Move the set of every cell the expedition could be in on a minute at a time, as rows of bits
+  Frontier,
+}
+
+/// Every cell the expedition could be in at some minute, with the rows of the inside of the valley
+/// laid out as in [Blizzards]
+#[derive(Clone, Debug, Eq, PartialEq)]
+struct Reachable {
+  start: bool,
+  end: bool,
+  cells: Vec<u64>,
+}
+
+/// Finds the quickest trips through the valley using the blizzards of one period
+pub struct Solver<'a> {
+  valley: &'a Valley,
+  blizzards: Blizzards,
+  engine: Engine,
+}
+
+impl<'a> Solver<'a> {
+  pub fn new(valley: &'a Valley) -> Solver<'a> {
+    Solver::with_engine(valley, Engine::Frontier)
+  }
+
+  pub fn with_engine(valley: &'a Valley, engine: Engine) -> Solver<'a> {
+    Solver { valley, blizzards: Blizzards::new(valley), engine }
+  }
+
+  pub fn blizzards(&self) -> &Blizzards {
+    &self.blizzards
+  }
+
+  /// Whether the expedition can be in the cell at the given minute, the entrance and exit always
+  /// being free
+  pub fn is_open(&self, p: &XY, t: i32) -> bool {
+    p == &self.valley.start || p == &self.valley.end || self.blizzards.is_free(p.x, p.y, t)
+  }
+
+  /// The quickest trip leaving the first waypoint at the given minute and visiting the rest in
+  /// order, if there is one, there is a first waypoint and all the waypoints are inside the valley
+  /// or its entrance and exit
+  pub fn trip(&self, waypoints: &[XY], start_t: i32) -> Option<Route> {
+    let first = waypoints.first()?;
+    if waypoints.iter().any(|p| p != &self.valley.start && p != &self.valley.end && self.bit(p).is_none()) {
+      return None;
+    }
+    let mut route = Route { start: start_t, positions: vec![first.clone()], arrivals: Vec::new() };
+    for leg in waypoints.windows(2) {
+      let positions = self.leg(&leg[0], &leg[1], route.end())?;
+      route.positions.extend(positions.into_iter().skip(1));
+      route.arrivals.push(route.end());
+    }
+    Some(route)
+  }
+
+  /// The positions at each minute of the quickest way from one cell to another
+  fn leg(&self, from: &XY, to: &XY, start_t: i32) -> Option<Vec<XY>> {
+    match self.engine {
+      Engine::States => self.search_states(from, to, start_t),
+      Engine::Frontier => self.search_frontier(from, to, start_t),
+    }
+  }
+
+  fn search_states(&self, from: &XY, to: &XY, start_t: i32) -> Option<Vec<XY>> {
+    let map_width = self.valley.width + 2;
+    let cells = (map_width * (self.valley.height + 2)) as usize;
+    let period = self.blizzards.period();
+    let index = |p: &XY, t: i32| t.rem_euclid(period as i32) as usize * cells + (p.y * map_width + p.x) as usize;
+    // How each state was reached, as one more than the index of the step taken, or 0 if it wasn't
+    let mut reached_by = vec![0_u8; period * cells];
+    let mut frontier = vec![from.clone()];
+    reached_by[index(from, start_t)] = 1;
+    let mut t = start_t;
+
+    while !frontier.is_empty() {
+      if let Some(p) = frontier.iter().find(|p| *p == to) {
+        let mut positions = vec![p.clone()];
+        for back in (start_t + 1..=t).rev() {
+          let (dx, dy) = STEPS[reached_by[index(positions.last().unwrap(), back)] as usize - 1];
+          positions.push(positions.last().unwrap().add(-dx, -dy));
+        }
+        positions.reverse();
+        return Some(positions);
+      }
+      t += 1;
+      let mut next = Vec::new();
+      for p in &frontier {
+        for (step, (dx, dy)) in STEPS.iter().enumerate() {
+          let q = p.add(*dx, *dy);
+          if q.x < 0 || q.y < 0 || q.x >= map_width || q.y >= self.valley.height + 2 || !self.is_open(&q, t) {
+            continue;
+          }
+          let i = index(&q, t);
+          if reached_by[i] == 0 {
+            reached_by[i] = step as u8 + 1;
+            next.push(q);
+          }
+        }
+      }
+      frontier = next;
+    }
+    None
+  }
+
+  /// The bit for the cell in the rows of [Reachable] and [Blizzards], if it's inside the valley
+  fn bit(&self, p: &XY) -> Option<(usize, u64)> {
+    if p.x <= 0 || p.x > self.valley.width || p.y <= 0 || p.y > self.valley.height {
+      return None;
+    }
+    let (x, y) = ((p.x - 1) as usize, (p.y - 1) as usize);
+    Some((y * self.blizzards.words + x / 64, 1 << (x % 64)))
+  }
+
+  fn reachable_at(&self, reachable: &Reachable, p: &XY) -> bool {
+    if p == &self.valley.start {
+      reachable.start
+    } else if p == &self.valley.end {
+      reachable.end
+    } else {
+      self.bit(p).is_some_and(|(i, bit)| reachable.cells[i] & bit != 0)
+    }
+  }
+
+  /// The cells that can be reached at minute `t` from those reachable the minute before
+  fn advance(&self, reachable: &Reachable, t: i32) -> Reachable {
+    let words = self.blizzards.words;
+    let height = self.valley.height as usize;
+    let mut cells = vec![0; reachable.cells.len()];
+    for y in 0..height {
+      let row = &reachable.cells[y * words..(y + 1) * words];
+      let free = self.blizzards.free_row(y, t);
+      for w in 0..words {
+        let right = (row[w] << 1) | if w > 0 { row[w - 1] >> 63 } else { 0 };
+        let left = (row[w] >> 1) | if w + 1 < words { row[w + 1] << 63 } else { 0 };
+        let down = if y > 0 { reachable.cells[(y - 1) * words + w] } else { 0 };
+        let up = if y + 1 < height { reachable.cells[(y + 1) * words + w] } else { 0 };
+        cells[y * words + w] = (row[w] | right | left | down | up) & free[w];
+      }
+    }
+    // Step in from the entrance and exit, which are always free
+    for (inside, outside) in [(self.valley.start.add(0, 1), reachable.start), (self.valley.end.add(0, -1), reachable.end)] {
+      if let Some((i, bit)) = self.bit(&inside).filter(|_| outside) {
+        cells[i] |= bit & self.blizzards.free_row(i / words, t)[i % words];
+      }
+    }
+    Reachable {
+      start: reachable.start || self.reachable_at(reachable, &self.valley.start.add(0, 1)),
+      end: reachable.end || self.reachable_at(reachable, &self.valley.end.add(0, -1)),
+      cells,
+    }
+  }
+
+  fn search_frontier(&self, from: &XY, to: &XY, start_t: i32) -> Option<Vec<XY>> {
+    let period = self.blizzards.period();
+    let mut reachable = Reachable {
+      start: from == &self.valley.start,
+      end: from == &self.valley.end,
+      cells: vec![0; self.blizzards.words * self.valley.height as usize],
+    };
+    if let Some((i, bit)) = self.bit(from) {
+      reachable.cells[i] |= bit;
+    }
+    let mut history = vec![reachable];
+
+    while !self.reachable_at(history.last().unwrap(), to) {
+      let next = self.advance(history.last().unwrap(), start_t + history.len() as i32);
+      // Once the cells repeat a blizzard period later they'll never include the destination
+      if history.len() >= period && history[history.len() - period] == next {
+        return None;
+      }
+      history.push(next);
+    }
+
+    // Walk back through the minutes to a cell reachable the minute before each
+    let mut positions = vec![to.clone()];
+    for reachable in history.iter().rev().skip(1) {
+      let p = positions.last().unwrap();
+      let previous = STEPS.iter()
+        .map(|(dx, dy)| p.add(-dx, -dy))
+        .find(|q| self.reachable_at(reachable, q))
+        .unwrap();
+      positions.push(previous);
+    }
+    positions.reverse();
+    Some(positions)
+  }
+}
+
 #[cfg(test)]
 mod tests {
-  use crate::day24::{CLEAR, generator, part1, part2, RIGHT_WIND, _WALL, XY};
+  use crate::day24::{Blizzards, CLEAR, Engine, generator, part1, part2, RIGHT_WIND, Solver, Valley, _WALL, XY};
 
   fn input() -> String {
     vec![
@@ -224,4 +526,74 @@ mod tests {
     let v = generator(input().as_str());
     assert_eq!(54, part2(&v));
   }
-}
\ No newline at end of file
+
+  #[test]
+  fn test_blizzards() {
+    let v = generator(input().as_str());
+    let blizzards = Blizzards::new(&v);
+    assert_eq!(12, blizzards.period());
+    for t in 0..30 {
+      for y in 0..v.height + 2 {
+        for x in 0..v.width + 2 {
+          assert_eq!(v.is_clear(x, y, t), blizzards.is_free(x, y, t), "({x}, {y}) at {t}");
+        }
+      }
+    }
+    assert_eq!(&[0b1101], blizzards.free_row(0, 4));
+  }
+
+  #[test]
+  fn test_trip() {
+    let v = generator(input().as_str());
+    for engine in [Engine::States, Engine::Frontier] {
+      check_trips(&v, &Solver::with_engine(&v, engine));
+    }
+  }
+
+  fn check_trips(v: &Valley, solver: &Solver) {
+    let route = solver.trip(&[v.start().clone(), v.end().clone()], 0).unwrap();
+    assert_eq!(18, route.end());
+    assert_eq!(vec![18], route.arrivals);
+    assert_eq!(19, route.positions.len());
+    for (t, p) in route.positions.iter().enumerate() {
+      assert!(solver.is_open(p, t as i32));
+    }
+    assert_eq!(Some("Minute 18, move down."), route.describe().lines().last());
+    assert_eq!(18, route.describe().lines().count());
+
+    let there_and_back = [v.start().clone(), v.end().clone(), v.start().clone(), v.end().clone()];
+    let route = solver.trip(&there_and_back, 0).unwrap();
+    assert_eq!(vec![18, 41, 54], route.arrivals);
+    assert_eq!(&there_and_back[2], &route.positions[41]);
+
+    // Via the bottom left corner of the valley, and starting later
+    let route = solver.trip(&[v.start().clone(), XY::new(1, 4), v.end().clone()], 3).unwrap();
+    assert_eq!(3, route.start);
+    assert_eq!(XY::new(1, 4), route.positions[(route.arrivals[0] - 3) as usize]);
+    assert!(route.end() >= 18);
+    assert!(solver.trip(&[v.start().clone(), XY::new(0, 0)], 0).is_none());
+    assert!(solver.trip(&[], 0).is_none());
+  }
+
+  #[test]
+  fn test_engines_agree() {
+    let v = generator(input().as_str());
+    let states = Solver::with_engine(&v, Engine::States);
+    let frontier = Solver::with_engine(&v, Engine::Frontier);
+    let cells: Vec<XY> = (0..v.height + 2).flat_map(|y| (0..v.width + 2).map(move |x| XY::new(x, y))).collect();
+    for from in &cells {
+      for to in &cells {
+        for t in [0, 5] {
+          let expected = states.trip(&[from.clone(), to.clone()], t).map(|r| r.end());
+          let route = frontier.trip(&[from.clone(), to.clone()], t);
+          assert_eq!(expected, route.as_ref().map(|r| r.end()), "{from:?} to {to:?} at {t}");
+          if let Some(route) = route {
+            for (i, p) in route.positions.iter().enumerate() {
+              assert!(i == 0 || frontier.is_open(p, t + i as i32));
+            }
+          }
+        }
+      }
+    }
+  }
+}
@@ -1,9 +1,14 @@
-fn to_dec(c: char) -> i32 {
+use std::cmp::Ordering;
+use std::fmt::{Display, Formatter};
+use std::ops;
+use std::str::FromStr;
+
+fn to_dec(c: char) -> Option<i32> {
   match c {
-    _ if c.is_digit(10) => c.to_digit(10).unwrap() as i32,
-    '-' => -1,
-    '=' => -2,
-    _ => panic!("Unexpected input {c}"),
+    '0'..='2' => c.to_digit(10).map(|d| d as i32),
+    '-' => Some(-1),
+    '=' => Some(-2),
+    _ => None,
   }
 }
 
@@ -18,8 +23,8 @@ fn to_char(n: i32) -> char {
   }
 }
 
-pub fn generator(input: &str) -> Vec<Vec<char>> {
-  input.lines().map(|l| l.chars().collect()).collect()
+pub fn generator(input: &str) -> Vec<Snafu> {
+  input.lines().map(|l| l.parse().expect("Expecting a SNAFU number")).collect()
 }
 
 fn handle_value(mut n: i32) -> (i32, i32) {
@@ -39,36 +44,244 @@ fn handle_value(mut n: i32) -> (i32, i32) {
   (cf, n)
 }
 
-pub fn part1(numbers: &Vec<Vec<char>>) -> String {
-  let mut result: Vec<char> = Vec::new();
-  let max_length = numbers.iter().map(|n| n.len()).max().unwrap();
-  let mut cf = 0;
-  for x in 0..max_length {
-    let mut n = cf;
-    for number in numbers {
-      if number.len() > x {
-        n += to_dec(number[number.len() - 1 - x]);
+pub fn part1(numbers: &[Snafu]) -> String {
+  numbers.iter().fold(Snafu::default(), |a, n| &a + n).to_string()
+}
+
+pub fn part2(_numbers: &[Snafu]) -> String {
+  "yay".to_string()
+}
+
+/// Why a string or number could not be converted
+#[derive(Debug, Eq, PartialEq)]
+pub enum SnafuError {
+  Empty,
+  /// A character other than `=`, `-`, `0`, `1` or `2` at the given position
+  InvalidDigit { digit: char, position: usize },
+  /// The number does not fit in the requested integer type
+  Overflow,
+}
+
+impl Display for SnafuError {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    match self {
+      SnafuError::Empty => write!(f, "empty SNAFU number"),
+      SnafuError::InvalidDigit { digit, position } =>
+        write!(f, "invalid SNAFU digit '{}' at position {}", digit, position),
+      SnafuError::Overflow => write!(f, "SNAFU number is too large"),
+    }
+  }
+}
+
+/// A number of any length in SNAFU, base five with the digits `=` (-2), `-` (-1), `0`, `1`
+/// and `2`. Digits are kept from the least significant up without leading zeros, so zero has
+/// no digits.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Hash)]
+pub struct Snafu {
+  digits: Vec<i8>,
+}
+
+impl Snafu {
+  /// Build a number from digits in any range, least significant first, carrying as needed
+  fn normalize(digits: Vec<i32>) -> Snafu {
+    let mut result = Vec::with_capacity(digits.len() + 1);
+    let mut cf = 0;
+    for d in digits {
+      let (carry, digit) = handle_value(d + cf);
+      cf = carry;
+      result.push(digit as i8);
+    }
+    while cf != 0 {
+      let (carry, digit) = handle_value(cf);
+      cf = carry;
+      result.push(digit as i8);
+    }
+    while result.last() == Some(&0) {
+      result.pop();
+    }
+    Snafu { digits: result }
+  }
+
+  pub fn is_zero(&self) -> bool {
+    self.digits.is_empty()
+  }
+
+  /// -1, 0 or 1, which is the sign of the most significant digit
+  pub fn signum(&self) -> i32 {
+    self.digits.last().map_or(0, |d| d.signum() as i32)
+  }
+}
+
+impl FromStr for Snafu {
+  type Err = SnafuError;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    if s.is_empty() {
+      return Err(SnafuError::Empty);
+    }
+    let digits = s.chars().enumerate().map(|(position, digit)| {
+      to_dec(digit).ok_or(SnafuError::InvalidDigit { digit, position })
+    }).collect::<Result<Vec<i32>, SnafuError>>()?;
+    Ok(Snafu::normalize(digits.into_iter().rev().collect()))
+  }
+}
+
+impl Display for Snafu {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    if self.is_zero() {
+      return write!(f, "0");
+    }
+    let s: String = self.digits.iter().rev().map(|&d| to_char(d as i32)).collect();
+    write!(f, "{}", s)
+  }
+}
+
+impl From<i128> for Snafu {
+  fn from(mut n: i128) -> Self {
+    let mut digits = Vec::new();
+    while n != 0 {
+      let mut digit = n % 5;
+      n /= 5;
+      if digit > 2 {
+        digit -= 5;
+        n += 1;
+      } else if digit < -2 {
+        digit += 5;
+        n -= 1;
       }
+      digits.push(digit as i8);
     }
-    let h = handle_value(n);
-    cf = h.0;
-    result.push(to_char(h.1))
+    Snafu { digits }
   }
-  while cf != 0 {
-    let h = handle_value(cf);
-    result.push(to_char(h.1));
-    cf = h.0;
+}
+
+impl From<i64> for Snafu {
+  fn from(n: i64) -> Self {
+    Snafu::from(n as i128)
   }
-  result.iter().rev().collect()
 }
 
-pub fn part2(_numbers: &Vec<Vec<char>>) -> String {
-  "yay".to_string()
+impl TryFrom<&Snafu> for i128 {
+  type Error = SnafuError;
+
+  fn try_from(value: &Snafu) -> Result<Self, Self::Error> {
+    // Every prefix of the digits has the same sign as the number, so the magnitude only grows
+    let sign = value.signum() as i128;
+    let magnitude = value.digits.iter().rev().try_fold(0u128, |n, &d| {
+      n.checked_mul(5).and_then(|n| n.checked_add_signed(sign * d as i128))
+    });
+    match magnitude {
+      Some(m) if sign < 0 => 0i128.checked_sub_unsigned(m),
+      Some(m) => i128::try_from(m).ok(),
+      None => None,
+    }.ok_or(SnafuError::Overflow)
+  }
+}
+
+impl TryFrom<&Snafu> for i64 {
+  type Error = SnafuError;
+
+  fn try_from(value: &Snafu) -> Result<Self, Self::Error> {
+    i128::try_from(value)?.try_into().map_err(|_| SnafuError::Overflow)
+  }
+}
+
+impl Ord for Snafu {
+  /// A longer number of the same sign is further from zero, as the leading digit outweighs all
+  /// those after it. Otherwise the most significant digit that differs decides.
+  fn cmp(&self, other: &Self) -> Ordering {
+    let by_len = self.digits.len().cmp(&other.digits.len());
+    self.signum().cmp(&other.signum())
+      .then(if self.signum() < 0 { by_len.reverse() } else { by_len })
+      .then_with(|| self.digits.iter().rev().cmp(other.digits.iter().rev()))
+  }
+}
+
+impl PartialOrd for Snafu {
+  fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+    Some(self.cmp(other))
+  }
+}
+
+impl ops::Add for &Snafu {
+  type Output = Snafu;
+
+  fn add(self, rhs: Self) -> Self::Output {
+    let len = self.digits.len().max(rhs.digits.len());
+    let digit = |n: &Snafu, i: usize| n.digits.get(i).map_or(0, |&d| d as i32);
+    Snafu::normalize((0..len).map(|i| digit(self, i) + digit(rhs, i)).collect())
+  }
+}
+
+impl ops::Sub for &Snafu {
+  type Output = Snafu;
+
+  fn sub(self, rhs: Self) -> Self::Output {
+    self + &-rhs
+  }
+}
+
+impl ops::Mul for &Snafu {
+  type Output = Snafu;
+
+  fn mul(self, rhs: Self) -> Self::Output {
+    if self.is_zero() || rhs.is_zero() {
+      return Snafu::default();
+    }
+    // Each column sums at most 4 * length products, which stays well within an i32
+    let mut digits = vec![0; self.digits.len() + rhs.digits.len()];
+    for (i, &l) in self.digits.iter().enumerate() {
+      for (j, &r) in rhs.digits.iter().enumerate() {
+        digits[i + j] += l as i32 * r as i32;
+      }
+    }
+    Snafu::normalize(digits)
+  }
+}
+
+impl ops::Neg for &Snafu {
+  type Output = Snafu;
+
+  fn neg(self) -> Self::Output {
+    Snafu { digits: self.digits.iter().map(|d| -d).collect() }
+  }
+}
+
+impl ops::Add for Snafu {
+  type Output = Snafu;
+
+  fn add(self, rhs: Self) -> Self::Output {
+    &self + &rhs
+  }
+}
+
+impl ops::Sub for Snafu {
+  type Output = Snafu;
+
+  fn sub(self, rhs: Self) -> Self::Output {
+    &self - &rhs
+  }
+}
+
+impl ops::Mul for Snafu {
+  type Output = Snafu;
+
+  fn mul(self, rhs: Self) -> Self::Output {
+    &self * &rhs
+  }
+}
+
+impl ops::Neg for Snafu {
+  type Output = Snafu;
+
+  fn neg(self) -> Self::Output {
+    -&self
+  }
 }
 
 #[cfg(test)]
 mod tests {
-  use crate::day25::{generator, handle_value, part1};
+  use crate::day25::{generator, handle_value, part1, Snafu, SnafuError};
 
   fn input() -> String {
     vec![
@@ -92,8 +305,8 @@ mod tests {
   fn test_generator() {
     let numbers = generator(input().as_str());
     assert_eq!(13, numbers.len());
-    assert_eq!("1=-0-2", numbers[0].iter().collect::<String>());
-    assert_eq!("122", numbers[12].iter().collect::<String>());
+    assert_eq!("1=-0-2", numbers[0].to_string());
+    assert_eq!("122", numbers[12].to_string());
   }
 
   #[test]
@@ -118,4 +331,77 @@ mod tests {
     let numbers = generator(input().as_str());
     assert_eq!("2=-1=0", part1(&numbers));
   }
-}
\ No newline at end of file
+
+  fn snafu(s: &str) -> Snafu {
+    s.parse().unwrap()
+  }
+
+  #[test]
+  fn test_conversion() {
+    let examples = [(1, "1"), (2, "2"), (3, "1="), (4, "1-"), (5, "10"), (8, "2="), (10, "20"),
+      (15, "1=0"), (20, "1-0"), (2022, "1=11-2"), (12345, "1-0---0"), (314159265, "1121-1110-1=0")];
+    for (n, s) in examples {
+      assert_eq!(s, Snafu::from(n as i64).to_string());
+      assert_eq!(Ok(n as i64), i64::try_from(&snafu(s)));
+      assert_eq!(format!("-{}", n), i64::try_from(&-&snafu(s)).unwrap().to_string());
+    }
+    assert_eq!("0", Snafu::from(0i64).to_string());
+    assert_eq!(Snafu::default(), snafu("000"));
+    assert_eq!("1=", snafu("001=").to_string());
+    assert_eq!(Ok(i128::MAX), i128::try_from(&Snafu::from(i128::MAX)));
+    assert_eq!(Ok(i128::MIN), i128::try_from(&Snafu::from(i128::MIN)));
+    assert_eq!(Ok(i64::MIN), i64::try_from(&Snafu::from(i64::MIN)));
+    assert_eq!(Err(SnafuError::Overflow), i64::try_from(&Snafu::from(i64::MAX as i128 + 1)));
+    let huge = &Snafu::from(i128::MAX) * &Snafu::from(i128::MAX);
+    assert_eq!(Err(SnafuError::Overflow), i128::try_from(&huge));
+  }
+
+  #[test]
+  fn test_parse_errors() {
+    assert_eq!(Err(SnafuError::Empty), "".parse::<Snafu>());
+    assert_eq!(Err(SnafuError::InvalidDigit { digit: '3', position: 2 }), "1=3".parse::<Snafu>());
+    assert_eq!("invalid SNAFU digit 'x' at position 0", "x".parse::<Snafu>().unwrap_err().to_string());
+  }
+
+  #[test]
+  fn test_arithmetic() {
+    let (a, b) = (Snafu::from(2022i64), Snafu::from(-314i64));
+    assert_eq!(Snafu::from(1708i64), &a + &b);
+    assert_eq!(Snafu::from(2336i64), &a - &b);
+    assert_eq!(Snafu::from(-634908i64), &a * &b);
+    assert_eq!(Snafu::from(314i64), -&b);
+    assert_eq!(Snafu::default(), &a - &a);
+    assert_eq!(Snafu::default(), &a * &Snafu::default());
+    assert!(b < a);
+    assert!(Snafu::default() > b);
+    assert!(snafu("1=") < snafu("1-"));
+    assert!(snafu("2=") > snafu("12"));
+    // Beyond the range of i128
+    let big = &Snafu::from(i128::MAX) + &Snafu::from(i128::MAX);
+    assert!(big > Snafu::from(i128::MAX));
+    assert_eq!(Snafu::from(i128::MAX), &(&big - &Snafu::from(i128::MAX)) * &Snafu::from(1i64));
+    let mut numbers = [snafu("1=-0-2"), snafu("12111"), snafu("2=0="), snafu("-")];
+    numbers.sort();
+    assert_eq!(vec!["-", "2=0=", "12111", "1=-0-2"],
+               numbers.iter().map(|n| n.to_string()).collect::<Vec<String>>());
+  }
+
+  #[test]
+  fn test_owned_arithmetic() {
+    let (a, b) = (Snafu::from(2022i64), Snafu::from(-314i64));
+    assert_eq!(Snafu::from(1708i64), a.clone() + b.clone());
+    assert_eq!(Snafu::from(2336i64), a.clone() - b.clone());
+    assert_eq!(Snafu::from(-634908i64), a.clone() * b.clone());
+    assert_eq!(Snafu::from(314i64), -b);
+    assert_eq!(Snafu::from(-2022i64), -a);
+  }
+
+  #[test]
+  fn test_ordering() {
+    for x in -300i64..300 {
+      for y in [-3126i64, -313, -63, -12, -3, 0, 2, 3, 12, 13, 62, 63, 312, 313, 3125] {
+        assert_eq!(x.cmp(&y), Snafu::from(x).cmp(&Snafu::from(y)), "{x} and {y}");
+      }
+    }
+  }
+}
@@ -1,45 +1,198 @@
+use std::fmt::{Display, Formatter};
 use regex::Regex;
 
-pub fn generator(input: &str) -> (Vec<Vec<char>>, Vec<(usize, usize, usize)>) {
+pub fn generator(input: &str) -> (Stacks, Vec<Move>) {
   let mut s = input.split("\n\n");
   let s1 = s.next().unwrap();
   let s2 = s.next().unwrap();
   if s.next().is_some() {
     panic!("Unexpected sections")
   }
-  (mk_stacks(s1), mk_moves(s2))
+  (Stacks::from(s1), mk_moves(s2))
 }
 
-pub fn part1(input: &(Vec<Vec<char>>, Vec<(usize, usize, usize)>)) -> String {
-  let mut stacks = input.0.clone();
-  for (times, from, to) in input.1.iter() {
-    for _ in 0..*times {
-      if let Some(c) = stacks.get_mut(*from).unwrap().pop() {
-       stacks.get_mut(*to).unwrap().push(c);
-      } else {
-        panic!("Unexpected, try to move out of empty stack");
+pub fn part1(input: &(Stacks, Vec<Move>)) -> String {
+  rearranged_tops(input, &CrateMover9000)
+}
+
+pub fn part2(input: &(Stacks, Vec<Move>)) -> String {
+  rearranged_tops(input, &CrateMover9001)
+}
+
+fn rearranged_tops(input: &(Stacks, Vec<Move>), crane: &dyn Crane) -> String {
+  input.0.rearrange(&input.1, crane).unwrap_or_else(|e| panic!("Invalid rearrangement: {e}")).tops()
+}
+
+/// Move the given number of crates from one stack to another, with the stacks numbered from 0
+pub type Move = (usize, usize, usize);
+
+/// How a crane lifts crates from one stack onto another
+pub trait Crane {
+  /// Move `count` crates from the top of `from` to the top of `to`. There are always at least
+  /// `count` crates on `from`.
+  fn lift(&self, from: &mut Vec<char>, to: &mut Vec<char>, count: usize);
+}
+
+/// Moves one crate at a time, so the moved crates end up in reverse order
+pub struct CrateMover9000;
+
+impl Crane for CrateMover9000 {
+  fn lift(&self, from: &mut Vec<char>, to: &mut Vec<char>, count: usize) {
+    CapacityLimited(1).lift(from, to, count)
+  }
+}
+
+/// Moves all the crates at once, so they keep their order
+pub struct CrateMover9001;
+
+impl Crane for CrateMover9001 {
+  fn lift(&self, from: &mut Vec<char>, to: &mut Vec<char>, count: usize) {
+    CapacityLimited(count).lift(from, to, count)
+  }
+}
+
+/// Moves up to the given number of crates at a time, each lift keeping its crates in order
+pub struct CapacityLimited(pub usize);
+
+impl Crane for CapacityLimited {
+  fn lift(&self, from: &mut Vec<char>, to: &mut Vec<char>, mut count: usize) {
+    let capacity = self.0.max(1);
+    while count > 0 {
+      let lift = capacity.min(count);
+      to.extend(from.drain(from.len() - lift..));
+      count -= lift;
+    }
+  }
+}
+
+/// Why a move could not be made
+#[derive(Debug, Eq, PartialEq)]
+pub enum InvalidMove {
+  NoSuchStack(usize),
+  NotEnoughCrates { stack: usize, wanted: usize, available: usize },
+}
+
+/// A move that could not be made, with its 0-based position in the list of moves
+#[derive(Debug, Eq, PartialEq)]
+pub struct MoveError {
+  pub step: usize,
+  pub reason: InvalidMove,
+}
+
+impl Display for MoveError {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    write!(f, "move {}: ", self.step + 1)?;
+    match self.reason {
+      InvalidMove::NoSuchStack(stack) => write!(f, "there is no stack {}", stack + 1),
+      InvalidMove::NotEnoughCrates { stack, wanted, available } =>
+        write!(f, "cannot move {} crates from stack {} holding {}", wanted, stack + 1, available),
+    }
+  }
+}
+
+/// Stacks of crates, each from the bottom up
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Stacks {
+  stacks: Vec<Vec<char>>,
+}
+
+impl Stacks {
+  pub fn len(&self) -> usize {
+    self.stacks.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.stacks.is_empty()
+  }
+
+  pub fn stack(&self, idx: usize) -> &[char] {
+    &self.stacks[idx]
+  }
+
+  /// The top crate of each stack, with a space for empty stacks
+  pub fn tops(&self) -> String {
+    self.stacks.iter().map(|s| *s.last().unwrap_or(&' ')).collect()
+  }
+
+  /// Make a single move, leaving the stacks untouched if it is invalid
+  pub fn apply(&mut self, (count, from, to): Move, crane: &dyn Crane) -> Result<(), InvalidMove> {
+    for stack in [from, to] {
+      if stack >= self.stacks.len() {
+        return Err(InvalidMove::NoSuchStack(stack));
       }
     }
+    let available = self.stacks[from].len();
+    if available < count {
+      return Err(InvalidMove::NotEnoughCrates { stack: from, wanted: count, available });
+    }
+    if from != to {
+      let mut source = std::mem::take(&mut self.stacks[from]);
+      crane.lift(&mut source, &mut self.stacks[to], count);
+      self.stacks[from] = source;
+    }
+    Ok(())
+  }
+
+  /// The stacks after making all the moves
+  pub fn rearrange(&self, moves: &[Move], crane: &dyn Crane) -> Result<Stacks, MoveError> {
+    let mut stacks = self.clone();
+    for (step, m) in moves.iter().enumerate() {
+      stacks.apply(*m, crane).map_err(|reason| MoveError { step, reason })?;
+    }
+    Ok(stacks)
+  }
+
+  /// The stacks after each of the moves in turn, ending at the first invalid move
+  pub fn replay<'a>(&self, moves: &'a [Move], crane: &'a dyn Crane) -> Replay<'a> {
+    Replay { stacks: self.clone(), moves: moves.iter().enumerate(), crane, failed: false }
   }
-  stacks.iter().map(|s|s.last().unwrap()).collect()
 }
 
-pub fn part2(input: &(Vec<Vec<char>>, Vec<(usize, usize, usize)>)) -> String {
-  let mut stacks = input.0.clone();
-  let mut buff: Vec<char> = Vec::new();
-  for (times, from, to) in input.1.iter() {
-    for _ in 0..*times {
-      if let Some(c) = stacks.get_mut(*from).unwrap().pop() {
-        buff.push(c);
-      } else {
-        panic!("Unexpected, try to move out of empty stack");
+pub struct Replay<'a> {
+  stacks: Stacks,
+  moves: std::iter::Enumerate<std::slice::Iter<'a, Move>>,
+  crane: &'a dyn Crane,
+  failed: bool,
+}
+
+impl Iterator for Replay<'_> {
+  type Item = Result<Stacks, MoveError>;
+
+  fn next(&mut self) -> Option<Self::Item> {
+    if self.failed {
+      return None;
+    }
+    let (step, m) = self.moves.next()?;
+    match self.stacks.apply(*m, self.crane) {
+      Ok(()) => Some(Ok(self.stacks.clone())),
+      Err(reason) => {
+        self.failed = true;
+        Some(Err(MoveError { step, reason }))
       }
     }
-    for _ in 0..*times {
-      stacks.get_mut(*to).unwrap().push(buff.pop().unwrap());
+  }
+}
+
+impl From<&str> for Stacks {
+  fn from(input: &str) -> Self {
+    Stacks { stacks: mk_stacks(input) }
+  }
+}
+
+/// Draw the stacks the same way as the puzzle input
+impl Display for Stacks {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    let height = self.stacks.iter().map(|s| s.len()).max().unwrap_or(0);
+    for row in (0..height).rev() {
+      let line: Vec<String> = self.stacks.iter().map(|s| match s.get(row) {
+        Some(c) => format!("[{c}]"),
+        None => "   ".to_string(),
+      }).collect();
+      writeln!(f, "{}", line.join(" ").trim_end())?;
     }
+    let numbers: Vec<String> = (1..=self.stacks.len()).map(|n| format!(" {n} ")).collect();
+    write!(f, "{}", numbers.join(" ").trim_end())
   }
-  stacks.iter().map(|s|s.last().unwrap()).collect()
 }
 
 fn mk_stacks(input: &str) -> Vec<Vec<char>> {
@@ -79,7 +232,8 @@ fn mk_moves(input: &str) -> Vec<(usize, usize, usize)> {
 
 #[cfg(test)]
 mod tests {
-  use crate::day5::{generator, part1, part2};
+  use crate::day5::{CapacityLimited, CrateMover9000, CrateMover9001, generator, InvalidMove, MoveError, part1,
+                    part2, Stacks};
 
   fn input() -> String {
     vec![
@@ -99,9 +253,9 @@ mod tests {
   fn test_stacks() {
     let (stacks, moves) = generator(input().as_str());
     assert_eq!(3, stacks.len());
-    assert_eq!(&'N', stacks[0].last().unwrap());
-    assert_eq!(&'D', stacks[1].last().unwrap());
-    assert_eq!(&'P', stacks[2].last().unwrap());
+    assert_eq!(&'N', stacks.stack(0).last().unwrap());
+    assert_eq!(&'D', stacks.stack(1).last().unwrap());
+    assert_eq!(&'P', stacks.stack(2).last().unwrap());
 
     assert_eq!(4, moves.len());
     assert_eq!(&(1, 1, 0), moves.first().unwrap());
@@ -119,4 +273,60 @@ mod tests {
     let result = part2(&mut generator(input().as_str()));
     assert_eq!("MCD", result);
   }
-}
\ No newline at end of file
+
+  #[test]
+  fn test_render() {
+    let (stacks, _) = generator(input().as_str());
+    let drawing = input().split("\n\n").next().unwrap().to_string();
+    assert_eq!(drawing, stacks.to_string());
+    assert_eq!(stacks, Stacks::from(stacks.to_string().as_str()));
+
+    let (stacks, moves) = generator(input().as_str());
+    let stacks = stacks.rearrange(&moves, &CrateMover9000).unwrap();
+    assert_eq!(["        [Z]", "        [N]", "        [D]", "[C] [M] [P]", " 1   2   3"].join("\n"),
+               stacks.to_string());
+  }
+
+  #[test]
+  fn test_cranes() {
+    let (stacks, moves) = generator(input().as_str());
+    let tops = |crane| stacks.rearrange(&moves, crane).unwrap().tops();
+    assert_eq!("CMZ", tops(&CrateMover9000));
+    assert_eq!("MCD", tops(&CrateMover9001));
+    assert_eq!("CMZ", tops(&CapacityLimited(1)));
+    assert_eq!("MCD", tops(&CapacityLimited(3)));
+    assert!(stacks.rearrange(&[(3, 0, 1)], &CrateMover9001).is_err());
+
+    // Moving three crates two at a time puts the bottom crate on top
+    let stacks = Stacks::from("[A]\n[B]\n[C]\n 1   2");
+    assert_eq!(&['B', 'A', 'C'], stacks.rearrange(&[(3, 0, 1)], &CapacityLimited(2)).unwrap().stack(1));
+    assert_eq!(&['A', 'B', 'C'], stacks.rearrange(&[(3, 0, 1)], &CrateMover9000).unwrap().stack(1));
+    assert_eq!(&['C', 'B', 'A'], stacks.rearrange(&[(3, 0, 1)], &CrateMover9001).unwrap().stack(1));
+    assert_eq!(" A", stacks.rearrange(&[(3, 0, 1)], &CrateMover9001).unwrap().tops());
+  }
+
+  #[test]
+  fn test_replay() {
+    let (stacks, moves) = generator(input().as_str());
+    let states: Vec<String> = stacks.replay(&moves, &CrateMover9000).map(|s| s.unwrap().tops()).collect();
+    assert_eq!(vec!["DCP", " CZ", "M Z", "CMZ"], states);
+
+    let moves = vec![(1, 1, 0), (5, 0, 2), (1, 0, 1)];
+    let mut replay = stacks.replay(&moves, &CrateMover9001);
+    assert_eq!("DCP", replay.next().unwrap().unwrap().tops());
+    let error = MoveError { step: 1, reason: InvalidMove::NotEnoughCrates { stack: 0, wanted: 5, available: 3 } };
+    assert_eq!(Some(Err(error)), replay.next());
+    assert_eq!(None, replay.next());
+
+    let error = stacks.rearrange(&[(1, 0, 3)], &CrateMover9000).unwrap_err();
+    assert_eq!(MoveError { step: 0, reason: InvalidMove::NoSuchStack(3) }, error);
+    assert_eq!("move 1: there is no stack 4", error.to_string());
+  }
+
+  #[test]
+  #[should_panic(expected = "Invalid rearrangement: move 2: cannot move 5 crates from stack 1 holding 3")]
+  fn test_invalid_rearrangement() {
+    let (stacks, _) = generator(input().as_str());
+    part1(&(stacks, vec![(1, 1, 0), (5, 0, 2), (1, 0, 1)]));
+  }
+}
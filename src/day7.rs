@@ -1,10 +1,14 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 
-pub fn generator(input: &str) -> HashMap<String, u64> {
-  compute_sizes(parse(input))
+pub mod transcript;
+
+pub fn generator(input: &str) -> FileSystem {
+  FileSystem::from_entries(parse(input))
 }
 
-fn parse(input: &str) -> Vec<(String, Option<u64>)> {
+/// Flatten a terminal session into the paths it lists, with the sizes of files and `None` for
+/// directories
+pub fn parse(input: &str) -> Vec<(String, Option<u64>)> {
   let mut pwd: Vec<&str> = Vec::new();
   let mut out: Vec<(String, Option<u64>)> = Vec::new();
   for line in input.lines() {
@@ -13,6 +17,7 @@ fn parse(input: &str) -> Vec<(String, Option<u64>)> {
         pwd.pop();
       }
       l if l.starts_with("$ cd /") => {
+        pwd.clear();
         pwd.push("");
         out.push(("/".to_string(), None))
       }
@@ -31,31 +36,16 @@ fn parse(input: &str) -> Vec<(String, Option<u64>)> {
   out
 }
 
-pub fn part1(sizes: &HashMap<String, u64>) -> u64 {
-  sizes.iter().filter_map(|(_, size)| if size < &100000 {
-    Some(size)
-  } else {
-    None
-  })
-    .sum()
+pub fn part1(fs: &FileSystem) -> u64 {
+  fs.small_dirs_total(&Limits::default())
 }
 
-pub fn part2(sizes: &HashMap<String, u64>) -> u64 {
-  let free_space = 70000000 - sizes["/"];
-  if free_space >= 30000000 {
-    return 0;
-  }
-  let need_space = 30000000 - free_space;
-  let mut found = u64::MAX;
-  for (_, size) in sizes {
-    if size > &need_space {
-      found = found.min(*size);
-    }
-  }
-  found
+pub fn part2(fs: &FileSystem) -> u64 {
+  fs.dir_to_delete(&Limits::default()).map_or(0, |e| e.size)
 }
 
-fn compute_sizes(input: Vec<(String, Option<u64>)>) -> HashMap<String, u64> {
+/// Sizes of every directory, keyed by its path with a trailing `/`
+pub fn compute_sizes(input: Vec<(String, Option<u64>)>) -> HashMap<String, u64> {
   let mut sizes: HashMap<String, u64> = HashMap::new();
   for (file, size) in input {
     if size.is_none() {
@@ -81,9 +71,215 @@ fn mk_name(parent: &[&str], name: &str) -> String {
   format!("{}/{}", parent.join("/"), name)
 }
 
+/// The disk and the thresholds the puzzle asks about
+#[derive(Clone, Debug)]
+pub struct Limits {
+  /// Total size of the disk
+  pub disk: u64,
+  /// Free space needed for the update
+  pub needed: u64,
+  /// Directories smaller than this are counted as small
+  pub threshold: u64,
+}
+
+impl Default for Limits {
+  fn default() -> Self {
+    Limits { disk: 70000000, needed: 30000000, threshold: 100000 }
+  }
+}
+
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Dir {
+  pub dirs: BTreeMap<String, Dir>,
+  pub files: BTreeMap<String, u64>,
+}
+
+impl Dir {
+  pub fn size(&self) -> u64 {
+    self.files.values().sum::<u64>() + self.dirs.values().map(|d| d.size()).sum::<u64>()
+  }
+
+  /// Add the entry at the given path below this directory, creating any missing parents. A later
+  /// listing of the same name replaces the earlier one.
+  fn insert(&mut self, path: &[&str], size: Option<u64>) {
+    match path {
+      [] => {}
+      [name] => match size {
+        Some(size) => {
+          self.dirs.remove(*name);
+          self.files.insert(name.to_string(), size);
+        }
+        None => {
+          if self.files.remove(*name).is_some() || !self.dirs.contains_key(*name) {
+            self.dirs.insert(name.to_string(), Dir::default());
+          }
+        }
+      },
+      [name, rest @ ..] => {
+        self.files.remove(*name);
+        self.dirs.entry(name.to_string()).or_default().insert(rest, size)
+      }
+    }
+  }
+
+  /// Collect the entries below this directory, returning its size
+  fn collect(&self, path: &str, out: &mut Vec<Entry>) -> u64 {
+    let idx = out.len();
+    out.push(Entry { path: path.to_string(), size: 0, is_dir: true });
+    let mut size = 0;
+    for (name, child) in &self.dirs {
+      size += child.collect(&join(path, name), out);
+    }
+    for (name, file_size) in &self.files {
+      out.push(Entry { path: join(path, name), size: *file_size, is_dir: false });
+      size += file_size;
+    }
+    out[idx].size = size;
+    size
+  }
+
+  fn print_tree(&self, name: &str, depth: usize, out: &mut String) {
+    out.push_str(&format!("{}- {} (dir)\n", "  ".repeat(depth), name));
+    let mut names: Vec<&String> = self.dirs.keys().chain(self.files.keys()).collect();
+    names.sort();
+    for name in names {
+      match self.files.get(name) {
+        Some(size) => out.push_str(&format!("{}- {} (file, size={})\n", "  ".repeat(depth + 1), name, size)),
+        None => self.dirs[name].print_tree(name, depth + 1, out),
+      }
+    }
+  }
+}
+
+fn join(parent: &str, name: &str) -> String {
+  if parent == "/" { format!("/{name}") } else { format!("{parent}/{name}") }
+}
+
+/// A file or directory with its total size
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Entry {
+  pub path: String,
+  pub size: u64,
+  pub is_dir: bool,
+}
+
+impl Entry {
+  pub fn name(&self) -> &str {
+    match self.path.rfind('/') {
+      Some(p) if self.path.len() > 1 => &self.path[p + 1..],
+      _ => &self.path,
+    }
+  }
+}
+
+/// The directory tree seen through a terminal session
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct FileSystem {
+  pub root: Dir,
+}
+
+impl FileSystem {
+  pub fn from_entries(entries: Vec<(String, Option<u64>)>) -> FileSystem {
+    let mut root = Dir::default();
+    for (path, size) in entries {
+      let parts: Vec<&str> = path.split('/').filter(|p| !p.is_empty()).collect();
+      root.insert(&parts, size);
+    }
+    FileSystem { root }
+  }
+
+  /// Every directory and file, parents before their contents
+  pub fn entries(&self) -> Vec<Entry> {
+    let mut out = Vec::new();
+    self.root.collect("/", &mut out);
+    out
+  }
+
+  pub fn dirs(&self) -> impl Iterator<Item=Entry> {
+    self.entries().into_iter().filter(|e| e.is_dir)
+  }
+
+  /// Sizes of every directory, keyed the same way as [compute_sizes]
+  pub fn dir_sizes(&self) -> HashMap<String, u64> {
+    self.dirs().map(|e| (join(&e.path, ""), e.size)).collect()
+  }
+
+  /// Draw the tree the way the puzzle does
+  pub fn tree(&self) -> String {
+    let mut out = String::new();
+    self.root.print_tree("/", 0, &mut out);
+    out
+  }
+
+  /// Directory sizes, largest first, in the style of `du -h`
+  pub fn du(&self) -> String {
+    let mut dirs: Vec<Entry> = self.dirs().collect();
+    dirs.sort_by(|a, b| b.size.cmp(&a.size).then_with(|| a.path.cmp(&b.path)));
+    dirs.iter().map(|e| format!("{}\t{}\n", human_size(e.size), e.path)).collect()
+  }
+
+  /// Entries matching a glob where `*` matches any run of characters within a name and `?` any one
+  /// character. Patterns with a `/` match whole paths, others just the names.
+  pub fn find(&self, pattern: &str) -> Vec<Entry> {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let whole_path = pattern.contains(&'/');
+    self.entries().into_iter().filter(|e| {
+      let text: Vec<char> = if whole_path { e.path.chars().collect() } else { e.name().chars().collect() };
+      glob_match(&pattern, &text)
+    }).collect()
+  }
+
+  /// Total size of the directories smaller than the threshold
+  pub fn small_dirs_total(&self, limits: &Limits) -> u64 {
+    self.dirs().filter(|e| e.size < limits.threshold).map(|e| e.size).sum()
+  }
+
+  /// The smallest directory bigger than the space still needed, or `None` when there is
+  /// already enough free space or nothing big enough
+  pub fn dir_to_delete(&self, limits: &Limits) -> Option<Entry> {
+    let free_space = limits.disk.saturating_sub(self.root.size());
+    if free_space >= limits.needed {
+      return None;
+    }
+    let need_space = limits.needed - free_space;
+    self.dirs().filter(|e| e.size > need_space).min_by_key(|e| e.size)
+  }
+}
+
+fn glob_match(pattern: &[char], text: &[char]) -> bool {
+  match pattern.split_first() {
+    None => text.is_empty(),
+    Some(('*', rest)) => {
+      (0..=text.len()).take_while(|&i| i == 0 || text[i - 1] != '/')
+        .any(|i| glob_match(rest, &text[i..]))
+    }
+    Some((p, rest)) => match text.split_first() {
+      Some((c, text_rest)) if *p == *c || (*p == '?' && *c != '/') => glob_match(rest, text_rest),
+      _ => false,
+    },
+  }
+}
+
+/// Format a size the way `du -h` does, rounding up to one decimal below 10 and whole units above
+fn human_size(size: u64) -> String {
+  const UNITS: [&str; 5] = ["", "K", "M", "G", "T"];
+  let mut value = size as f64;
+  let mut unit = 0;
+  while value >= 1024.0 && unit < UNITS.len() - 1 {
+    value /= 1024.0;
+    unit += 1;
+  }
+  let tenths = (value * 10.0).ceil() / 10.0;
+  match unit {
+    0 => size.to_string(),
+    _ if tenths < 10.0 => format!("{:.1}{}", tenths, UNITS[unit]),
+    _ => format!("{}{}", value.ceil(), UNITS[unit]),
+  }
+}
+
 #[cfg(test)]
 mod tests {
-  use crate::day7::{generator, parse, part1, part2};
+  use crate::day7::{compute_sizes, generator, human_size, Limits, parse, part1, part2};
 
   fn input() -> String {
     vec![
@@ -128,7 +324,7 @@ mod tests {
 
   #[test]
   fn test_generator() {
-    let sizes = generator(input().as_str());
+    let sizes = generator(input().as_str()).dir_sizes();
     assert_eq!(4, sizes.len());
     assert_eq!(584, sizes["/a/e/"]);
     assert_eq!(94853, sizes["/a/"]);
@@ -147,4 +343,76 @@ mod tests {
     let input = generator(input().as_str());
     assert_eq!(24933642, part2(&input));
   }
-}
\ No newline at end of file
+
+  #[test]
+  fn test_tree() {
+    let fs = generator(input().as_str());
+    assert_eq!(vec![
+      "- / (dir)",
+      "  - a (dir)",
+      "    - e (dir)",
+      "      - i (file, size=584)",
+      "    - f (file, size=29116)",
+      "    - g (file, size=2557)",
+      "    - h.lst (file, size=62596)",
+      "  - b.txt (file, size=14848514)",
+      "  - c.dat (file, size=8504156)",
+      "  - d (dir)",
+      "    - d.ext (file, size=5626152)",
+      "    - d.log (file, size=8033020)",
+      "    - j (file, size=4060174)",
+      "    - k (file, size=7214296)",
+      "",
+    ].join("\n"), fs.tree());
+    assert_eq!(compute_sizes(parse(input().as_str())), fs.dir_sizes());
+  }
+
+  #[test]
+  fn test_transcript_quirks() {
+    let fs = generator(input().as_str());
+    // Returning to the root, listing again and a conflicting size for a file
+    let more = [input().as_str(), "$ cd /", "$ cd a", "$ ls", "dir e", "29116 f", "1000 g", "$ cd /", "$ ls",
+      "dir a"].join("\n");
+    let updated = generator(more.as_str());
+    assert_eq!(fs.root.size() - 2557 + 1000, updated.root.size());
+    assert_eq!(fs.root.dirs["d"], updated.root.dirs["d"]);
+    assert_eq!(1000, updated.root.dirs["a"].files["g"]);
+    assert_eq!(fs.entries().len(), updated.entries().len());
+  }
+
+  #[test]
+  fn test_du() {
+    let fs = generator(input().as_str());
+    assert_eq!("47M\t/\n24M\t/d\n93K\t/a\n584\t/a/e\n", fs.du());
+    assert_eq!("1.0K", human_size(1024));
+    assert_eq!("1.1K", human_size(1025));
+    assert_eq!("10K", human_size(10239));
+  }
+
+  #[test]
+  fn test_find() {
+    let fs = generator(input().as_str());
+    let names = |pattern| fs.find(pattern).iter().map(|e| e.path.clone()).collect::<Vec<String>>();
+    assert_eq!(vec!["/d/d.ext", "/d/d.log"], names("d.*"));
+    assert_eq!(vec!["/a/h.lst", "/d/d.ext", "/d/d.log", "/b.txt", "/c.dat"], names("?.*"));
+    assert_eq!(vec!["/a/e/i"], names("/*/*/*"));
+    assert_eq!(vec!["/a/e", "/a/f", "/a/g", "/a/h.lst"], names("/a/*"));
+    assert_eq!(vec!["/"], names("/"));
+    assert!(names("x*").is_empty());
+  }
+
+  #[test]
+  fn test_limits() {
+    let fs = generator(input().as_str());
+    let limits = Limits { threshold: 585, ..Limits::default() };
+    assert_eq!(584, fs.small_dirs_total(&limits));
+    let limits = Limits { threshold: 584, ..Limits::default() };
+    assert_eq!(0, fs.small_dirs_total(&limits));
+    let limits = Limits { needed: 1, ..Limits::default() };
+    assert_eq!(None, fs.dir_to_delete(&limits));
+    let limits = Limits { disk: 48381165, needed: 90000, ..Limits::default() };
+    assert_eq!("/a", fs.dir_to_delete(&limits).unwrap().path);
+    let limits = Limits { disk: 48381165, needed: 94853, ..Limits::default() };
+    assert_eq!("/d", fs.dir_to_delete(&limits).unwrap().path);
+  }
+}
@@ -0,0 +1,248 @@
+//! Checking terminal transcripts and writing them back out from a directory tree
+
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use crate::day7::{Dir, FileSystem};
+
+/// Something wrong with a transcript, with the 1-based line it was found on
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum TranscriptError {
+  /// `cd` into a directory that no `ls` of the current directory has shown
+  UnknownDirectory { line: usize, path: String },
+  /// `cd ..` while already at the root
+  AboveRoot { line: usize },
+  /// A file listed again with a different size
+  ConflictingSize { line: usize, path: String, first: u64, second: u64 },
+  /// A name listed as a file in one place and as a directory in another
+  ConflictingKind { line: usize, path: String },
+  /// Listing output that does not follow a `$ ls`
+  OutputWithoutLs { line: usize },
+  /// A line that is neither a known command nor a listing
+  Malformed { line: usize, text: String },
+}
+
+impl Display for TranscriptError {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    match self {
+      TranscriptError::UnknownDirectory { line, path } =>
+        write!(f, "line {line}: cd into unknown directory {path}"),
+      TranscriptError::AboveRoot { line } => write!(f, "line {line}: cd .. from the root"),
+      TranscriptError::ConflictingSize { line, path, first, second } =>
+        write!(f, "line {line}: {path} listed with size {second} after {first}"),
+      TranscriptError::ConflictingKind { line, path } =>
+        write!(f, "line {line}: {path} listed both as a file and a directory"),
+      TranscriptError::OutputWithoutLs { line } => write!(f, "line {line}: output without a preceding $ ls"),
+      TranscriptError::Malformed { line, text } => write!(f, "line {line}: cannot read '{text}'"),
+    }
+  }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Kind {
+  Dir,
+  File(u64),
+}
+
+/// Check a transcript, starting at the root, returning every problem found in order
+pub fn validate(input: &str) -> Result<(), Vec<TranscriptError>> {
+  let mut errors = Vec::new();
+  let mut pwd: Vec<&str> = Vec::new();
+  let mut known: HashMap<String, Kind> = HashMap::from([("/".to_string(), Kind::Dir)]);
+  let mut listing = false;
+  for (idx, text) in input.lines().enumerate() {
+    let line = idx + 1;
+    if let Some(command) = text.strip_prefix("$ ") {
+      listing = false;
+      match command.split_once(' ') {
+        None if command == "ls" => listing = true,
+        Some(("cd", "/")) => pwd.clear(),
+        Some(("cd", "..")) => {
+          if pwd.pop().is_none() {
+            errors.push(TranscriptError::AboveRoot { line });
+          }
+        }
+        Some(("cd", name)) if !name.contains('/') => {
+          let path = path_of(&pwd, name);
+          match known.get(&path) {
+            Some(Kind::Dir) => {}
+            Some(Kind::File(_)) => errors.push(TranscriptError::ConflictingKind { line, path }),
+            None => errors.push(TranscriptError::UnknownDirectory { line, path }),
+          }
+          // Carry on from where the transcript says it went
+          pwd.push(name);
+        }
+        _ => errors.push(TranscriptError::Malformed { line, text: text.to_string() }),
+      }
+      continue;
+    }
+    let kind = match text.split_once(' ') {
+      Some(("dir", name)) => Some((name, Kind::Dir)),
+      Some((size, name)) => size.parse().ok().map(|s| (name, Kind::File(s))),
+      None => None,
+    };
+    let (name, kind) = match kind {
+      Some((name, kind)) if !name.is_empty() && !name.contains('/') => (name, kind),
+      _ => {
+        errors.push(TranscriptError::Malformed { line, text: text.to_string() });
+        continue;
+      }
+    };
+    if !listing {
+      errors.push(TranscriptError::OutputWithoutLs { line });
+    }
+    let path = path_of(&pwd, name);
+    match (known.get(&path), kind) {
+      (Some(Kind::File(first)), Kind::File(second)) if *first != second => {
+        errors.push(TranscriptError::ConflictingSize { line, path, first: *first, second });
+      }
+      (Some(Kind::File(_)), Kind::Dir) | (Some(Kind::Dir), Kind::File(_)) => {
+        errors.push(TranscriptError::ConflictingKind { line, path });
+      }
+      (Some(_), _) => {}
+      (None, kind) => {
+        known.insert(path, kind);
+      }
+    }
+  }
+  if errors.is_empty() { Ok(()) } else { Err(errors) }
+}
+
+fn path_of(pwd: &[&str], name: &str) -> String {
+  format!("/{}", pwd.iter().chain([&name]).copied().collect::<Vec<&str>>().join("/"))
+}
+
+/// Write a transcript that explores the whole tree, listing each directory once in name order
+/// and not climbing back up after the last directory
+pub fn transcript(fs: &FileSystem) -> String {
+  let mut lines = vec!["$ cd /".to_string()];
+  write_dir(&fs.root, &mut lines);
+  while lines.last().is_some_and(|l| l == "$ cd ..") {
+    lines.pop();
+  }
+  lines.join("\n")
+}
+
+fn write_dir(dir: &Dir, lines: &mut Vec<String>) {
+  lines.push("$ ls".to_string());
+  let mut names: Vec<&String> = dir.dirs.keys().chain(dir.files.keys()).collect();
+  names.sort();
+  for name in names {
+    match dir.files.get(name) {
+      Some(size) => lines.push(format!("{size} {name}")),
+      None => lines.push(format!("dir {name}")),
+    }
+  }
+  for (name, child) in &dir.dirs {
+    lines.push(format!("$ cd {name}"));
+    write_dir(child, lines);
+    lines.push("$ cd ..".to_string());
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use rand::{Rng, SeedableRng};
+  use rand::rngs::StdRng;
+  use crate::day7::{compute_sizes, Dir, FileSystem, generator, parse};
+  use crate::day7::transcript::{transcript, TranscriptError, validate};
+
+  fn input() -> String {
+    vec![
+      "$ cd /",
+      "$ ls",
+      "dir a",
+      "14848514 b.txt",
+      "8504156 c.dat",
+      "dir d",
+      "$ cd a",
+      "$ ls",
+      "dir e",
+      "29116 f",
+      "2557 g",
+      "62596 h.lst",
+      "$ cd e",
+      "$ ls",
+      "584 i",
+      "$ cd ..",
+      "$ cd ..",
+      "$ cd d",
+      "$ ls",
+      "4060174 j",
+      "8033020 d.log",
+      "5626152 d.ext",
+      "7214296 k",
+    ].join("\n")
+  }
+
+  #[test]
+  fn test_validate() {
+    assert_eq!(Ok(()), validate(input().as_str()));
+    let bad = vec![
+      "$ cd /",
+      "$ cd x",
+      "$ ls",
+      "dir a",
+      "10 b",
+      "$ cd a",
+      "3 c",
+      "$ cd /",
+      "$ ls",
+      "dir a",
+      "11 b",
+      "12 b",
+      "dir b",
+      "$ cd ..",
+      "$ rm -rf a",
+      "$ ls",
+      "ten d",
+    ].join("\n");
+    assert_eq!(Err(vec![
+      TranscriptError::UnknownDirectory { line: 2, path: "/x".to_string() },
+      TranscriptError::OutputWithoutLs { line: 7 },
+      TranscriptError::ConflictingSize { line: 12, path: "/b".to_string(), first: 11, second: 12 },
+      TranscriptError::ConflictingKind { line: 13, path: "/b".to_string() },
+      TranscriptError::AboveRoot { line: 14 },
+      TranscriptError::Malformed { line: 15, text: "$ rm -rf a".to_string() },
+      TranscriptError::Malformed { line: 17, text: "ten d".to_string() },
+    ]), validate(bad.as_str()));
+    assert_eq!("line 2: cd into unknown directory /x",
+               validate("$ cd /\n$ cd x").unwrap_err()[0].to_string());
+  }
+
+  #[test]
+  fn test_round_trip() {
+    let fs = generator(input().as_str());
+    let sorted = input().replace("4060174 j\n8033020 d.log\n5626152 d.ext", "5626152 d.ext\n8033020 d.log\n4060174 j");
+    assert_eq!(sorted, transcript(&fs));
+    assert_eq!(fs, generator(transcript(&fs).as_str()));
+  }
+
+  fn random_dir(rng: &mut StdRng, depth: u32) -> Dir {
+    let mut dir = Dir::default();
+    for i in 0..rng.gen_range(0..5) {
+      dir.files.insert(format!("f{i}.txt"), rng.gen_range(1..200000));
+    }
+    if depth < 4 {
+      for i in 0..rng.gen_range(0..4) {
+        dir.dirs.insert(format!("d{i}"), random_dir(rng, depth + 1));
+      }
+    }
+    dir
+  }
+
+  #[test]
+  fn test_random_transcripts() {
+    let mut rng = StdRng::seed_from_u64(7);
+    for _ in 0..50 {
+      let fs = FileSystem { root: random_dir(&mut rng, 0) };
+      let text = transcript(&fs);
+      assert_eq!(Ok(()), validate(text.as_str()));
+      assert_eq!(fs, generator(text.as_str()));
+      // Empty directories have no size of their own in the flattened computation
+      let mut expected = fs.dir_sizes();
+      let sizes = compute_sizes(parse(text.as_str()));
+      expected.retain(|_, size| *size > 0);
+      assert_eq!(expected, sizes);
+    }
+  }
+}
@@ -1,3 +1,4 @@
+use colored::Colorize;
 
 pub fn generator(input: &str) -> Vec<Vec<i8>> {
   let trees: Vec<Vec<i8>> = input.lines()
@@ -44,9 +45,11 @@ fn compute_max(trees: &Vec<Vec<i8>>) -> Vec<Vec<i8>> {
   maxs
 }
 
-fn compute_scenic_score(trees: &Vec<Vec<i8>>) -> Vec<Vec<u32>> {
+/// Viewing distances of every tree looking in each direction, indexed by [Direction]
+fn compute_viewing_distances(trees: &[Vec<i8>]) -> [Vec<Vec<u32>>; 4] {
   let size = trees.len();
-  let mut scenic_score: Vec<Vec<u32>> = vec![vec![1; size]; size];
+  let mut distances: [Vec<Vec<u32>>; 4] = std::array::from_fn(|_| vec![vec![0; size]; size]);
+  let [up, down, left, right] = &mut distances;
   // Handle rows
   let mut m_lr: Vec<u32> = vec![0; 10];
   let mut m_rl: Vec<u32> = vec![0; 10];
@@ -64,23 +67,30 @@ fn compute_scenic_score(trees: &Vec<Vec<i8>>) -> Vec<Vec<u32>> {
       r = i;
       c = j;
       // left -> right
-      scenic_score[r][c] = scenic_score[r][c] * (j as u32 - m_lr[trees[r][c] as usize]);
+      left[r][c] = j as u32 - m_lr[trees[r][c] as usize];
       m_lr[..=trees[r][c] as usize].fill(j as u32);
       // up -> down
-      scenic_score[c][r] = scenic_score[c][r] * (j as u32 - m_ud[trees[c][r] as usize]);
+      up[c][r] = j as u32 - m_ud[trees[c][r] as usize];
       m_ud[..=trees[c][r] as usize].fill(j as u32);
 
       r = size - 1 - i;
       c = size - 1 - j;
       // right -> left
-      scenic_score[r][c] = scenic_score[r][c] * (j as u32 - m_rl[trees[r][c] as usize]);
+      right[r][c] = j as u32 - m_rl[trees[r][c] as usize];
       m_rl[..=trees[r][c] as usize].fill(j as u32);
       // down -> up
-      scenic_score[c][r] = scenic_score[c][r] * (j as u32 - m_du[trees[c][r] as usize]);
+      down[c][r] = j as u32 - m_du[trees[c][r] as usize];
       m_du[..=trees[c][r] as usize].fill(j as u32);
     }
   }
-  scenic_score
+  distances
+}
+
+fn compute_scenic_score(trees: &Vec<Vec<i8>>) -> Vec<Vec<u32>> {
+  let [up, down, left, right] = compute_viewing_distances(trees);
+  (0..trees.len()).map(|r| (0..trees.len()).map(|c| {
+    up[r][c] * down[r][c] * left[r][c] * right[r][c]
+  }).collect()).collect()
 }
 
 pub fn part1(trees: &Vec<Vec<i8>>) -> isize {
@@ -102,9 +112,110 @@ pub fn part2(trees: &Vec<Vec<i8>>) -> u32 {
   *scenic_scores.iter().flatten().max().unwrap()
 }
 
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Direction {
+  Up,
+  Down,
+  Left,
+  Right,
+}
+
+/// A tree with how far it can see in each direction, indexed by [Direction]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Tree {
+  pub row: usize,
+  pub col: usize,
+  pub height: i8,
+  pub score: u32,
+  pub distances: [u32; 4],
+}
+
+impl Tree {
+  pub fn distance(&self, direction: Direction) -> u32 {
+    self.distances[direction as usize]
+  }
+}
+
+/// Everything the two puzzle passes know about the forest
+pub struct Analysis {
+  pub heights: Vec<Vec<i8>>,
+  /// Whether each tree can be seen from outside the grid
+  pub visible: Vec<Vec<bool>>,
+  pub scores: Vec<Vec<u32>>,
+  distances: [Vec<Vec<u32>>; 4],
+}
+
+impl Analysis {
+  pub fn new(trees: &Vec<Vec<i8>>) -> Analysis {
+    let maxs = compute_max(trees);
+    let visible = trees.iter().zip(&maxs)
+      .map(|(row, max)| row.iter().zip(max).map(|(t, m)| t > m).collect())
+      .collect();
+    let distances = compute_viewing_distances(trees);
+    let size = trees.len();
+    let scores = (0..size).map(|r| (0..size).map(|c| {
+      distances.iter().map(|d| d[r][c]).product()
+    }).collect()).collect();
+    Analysis { heights: trees.clone(), visible, scores, distances }
+  }
+
+  pub fn visible_count(&self) -> usize {
+    self.visible.iter().flatten().filter(|v| **v).count()
+  }
+
+  /// The viewing distances of every tree looking in the given direction
+  pub fn distances(&self, direction: Direction) -> &Vec<Vec<u32>> {
+    &self.distances[direction as usize]
+  }
+
+  pub fn tree(&self, row: usize, col: usize) -> Tree {
+    Tree {
+      row,
+      col,
+      height: self.heights[row][col],
+      score: self.scores[row][col],
+      distances: std::array::from_fn(|d| self.distances[d][row][col]),
+    }
+  }
+
+  /// The k trees with the best scenic scores, ties going to the first in reading order
+  pub fn top(&self, k: usize) -> Vec<Tree> {
+    let size = self.heights.len();
+    let mut cells: Vec<(usize, usize)> = (0..size).flat_map(|r| (0..size).map(move |c| (r, c))).collect();
+    cells.sort_by_key(|&(r, c)| std::cmp::Reverse(self.scores[r][c]));
+    cells.iter().take(k).map(|&(r, c)| self.tree(r, c)).collect()
+  }
+
+  /// Bucket the scores into the given number of levels on a log scale, 0 holding only the trees
+  /// that cannot see past the edge. There must be at least two levels.
+  pub fn heat_levels(&self, levels: u8) -> Vec<Vec<u8>> {
+    assert!(levels >= 2, "Expecting at least 2 heat levels, got {levels}");
+    let max = self.scores.iter().flatten().max().copied().unwrap_or(0);
+    let top = levels - 1;
+    self.scores.iter().map(|row| row.iter().map(|&score| match score {
+      0 => 0,
+      _ if max <= 1 => top,
+      _ => 1 + ((top - 1) as f64 * (score as f64).ln() / (max as f64).ln()).round() as u8,
+    }).collect()).collect()
+  }
+
+  /// The tree heights on a background shading from blue for poor views to red for the best
+  pub fn heat_map(&self) -> String {
+    const LEVELS: u8 = 10;
+    self.heat_levels(LEVELS).iter().zip(&self.heights).map(|(levels, heights)| {
+      levels.iter().zip(heights).map(|(level, height)| {
+        let t = *level as f64 / (LEVELS - 1) as f64;
+        let (r, g, b) = ((255.0 * t) as u8, (255.0 * (1.0 - (2.0 * t - 1.0).abs())) as u8, (255.0 * (1.0 - t)) as u8);
+        height.to_string().black().on_truecolor(r, g, b).to_string()
+      }).collect::<String>()
+    }).collect::<Vec<String>>().join("\n")
+  }
+}
+
 #[cfg(test)]
 mod tests {
-  use crate::day8::{compute_max, compute_scenic_score, generator, part1, part2};
+  use regex::Regex;
+  use crate::day8::{Analysis, compute_max, compute_scenic_score, Direction, generator, part1, part2};
 
   fn input() -> String {
     vec![
@@ -154,4 +265,42 @@ mod tests {
     let trees = generator(input().as_str());
     assert_eq!(8, part2(&trees));
   }
-}
\ No newline at end of file
+
+  #[test]
+  fn test_analysis() {
+    let trees = generator(input().as_str());
+    let analysis = Analysis::new(&trees);
+    assert_eq!(21, analysis.visible_count());
+    assert_eq!(vec![true, true, true, false, true], analysis.visible[1]);
+    assert_eq!(compute_scenic_score(&trees), analysis.scores);
+
+    let best = &analysis.top(2);
+    assert_eq!((3, 2, 5, 8), (best[0].row, best[0].col, best[0].height, best[0].score));
+    assert_eq!([2, 1, 2, 2], best[0].distances);
+    assert_eq!((2, 1, 6), (best[1].row, best[1].col, best[1].score));
+    assert_eq!(1, best[1].distance(Direction::Up));
+    assert_eq!(2, best[1].distance(Direction::Down));
+    assert_eq!(1, best[1].distance(Direction::Left));
+    assert_eq!(3, best[1].distance(Direction::Right));
+    assert_eq!(4, analysis.tree(1, 2).score);
+    assert_eq!(vec![0, 1, 1, 1, 1], analysis.distances(Direction::Left)[2]);
+    assert_eq!(25, analysis.top(30).len());
+  }
+
+  #[test]
+  fn test_heat_map() {
+    let analysis = Analysis::new(&generator(input().as_str()));
+    let levels = analysis.heat_levels(10);
+    assert_eq!(vec![0; 5], levels[0]);
+    assert_eq!(9, levels[3][2]);
+    assert_eq!(vec![0, 1, 6, 1, 0], levels[1]);
+    let plain = Regex::new("\x1b\\[[0-9;]*m").unwrap().replace_all(&analysis.heat_map(), "").to_string();
+    assert_eq!(input(), plain);
+  }
+
+  #[test]
+  #[should_panic(expected = "Expecting at least 2 heat levels, got 1")]
+  fn test_too_few_heat_levels() {
+    Analysis::new(&generator(input().as_str())).heat_levels(1);
+  }
+}
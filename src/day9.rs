@@ -1,6 +1,7 @@
-use std::cell::RefCell;
 use std::collections::HashSet;
 
+pub type Position = (i32, i32);
+
 pub fn generator(input: &str) -> Vec<(char, i32)> {
   input.lines()
     .map(|l| {
@@ -10,129 +11,152 @@ pub fn generator(input: &str) -> Vec<(char, i32)> {
     }).collect()
 }
 
-pub fn part1(moves: &Vec<(char, i32)>) -> usize {
-  let mut visit_pos: HashSet<(i32, i32)> = HashSet::new();
-  let mut tail: (i32, i32) = (0, 0);
-  let mut head: (i32, i32) = (0, 0);
-  visit_pos.insert(tail);
+pub fn part1(moves: &[(char, i32)]) -> usize {
+  let mut rope = Rope::new(2);
+  rope.apply_all(moves);
+  rope.tail_visited().len()
+}
 
-  for (dir, steps) in moves {
-    move_head(&mut head, dir, steps);
-    move_knot_w_record(&mut tail, &head, &mut visit_pos);
-  }
-  visit_pos.len()
+pub fn part2(moves: &[(char, i32)]) -> usize {
+  let mut rope = Rope::new(10);
+  rope.apply_all(moves);
+  rope.tail_visited().len()
 }
 
-fn move_head(head: &mut (i32, i32), dir: &char, steps: &i32) {
+fn direction(dir: char) -> Position {
   match dir {
-    'R' => head.0 += steps,
-    'L' => head.0 -= steps,
-    'U' => head.1 += steps,
-    'D' => head.1 -= steps,
+    'R' => (1, 0),
+    'L' => (-1, 0),
+    'U' => (0, 1),
+    'D' => (0, -1),
     _ => panic!("Unexpected direction {dir}"),
   }
 }
 
-fn record_position(pos: &(i32, i32), positions: &mut HashSet<(i32, i32)>) {
-  positions.insert(*pos);
+/// A rope of knots starting at the origin, with the head first, recording every position each
+/// knot has visited
+#[derive(Clone, Debug)]
+pub struct Rope {
+  knots: Vec<Position>,
+  visited: Vec<HashSet<Position>>,
 }
 
-fn dont_record_position(_pos: &(i32, i32), _positions: &mut HashSet<(i32, i32)>) {
-  // do nothing
-}
+impl Rope {
+  pub fn new(knot_count: usize) -> Rope {
+    assert!(knot_count > 0, "A rope needs at least one knot");
+    Rope {
+      knots: vec![(0, 0); knot_count],
+      visited: vec![HashSet::from([(0, 0)]); knot_count],
+    }
+  }
 
-fn move_knot_w_record(knot: &mut (i32, i32), prev: &(i32, i32),
-                      visited_pos: &mut HashSet<(i32, i32)>) -> bool {
-  move_knot(knot, prev, visited_pos, record_position)
-}
+  pub fn knots(&self) -> &[Position] {
+    &self.knots
+  }
 
-fn move_knot_wo_record(knot: &mut (i32, i32), prev: &(i32, i32),
-                       visited_pos: &mut HashSet<(i32, i32)>) -> bool {
-  move_knot(knot, prev, visited_pos, dont_record_position)
-}
+  /// The positions visited by the knot at the given index, the head being 0
+  pub fn visited(&self, knot: usize) -> &HashSet<Position> {
+    &self.visited[knot]
+  }
 
-fn move_knot(knot: &mut (i32, i32), prev: &(i32, i32),
-             visited_pos: &mut HashSet<(i32, i32)>,
-             record: fn(&(i32, i32), &mut HashSet<(i32, i32)>)) -> bool {
-  let x_diff = knot.0.abs_diff(prev.0);
-  let y_diff = knot.1.abs_diff(prev.1);
-  match (x_diff, y_diff) {
-    (x_diff, y_diff) if x_diff < 2 && y_diff < 2 => {
-      // Touching, no movement required
-      return false;
-    }
-    (0, y_diff) => {
-      // Same row
-      let y_c = (prev.1 - knot.1) / y_diff as i32;
-      for _ in 0..(y_diff - 1) {
-        knot.1 += y_c;
-        record(knot, visited_pos);
+  pub fn tail_visited(&self) -> &HashSet<Position> {
+    self.visited.last().unwrap()
+  }
+
+  /// Move the head a single step in the given direction and let the other knots follow
+  pub fn step(&mut self, dir: char) {
+    let (dx, dy) = direction(dir);
+    self.knots[0].0 += dx;
+    self.knots[0].1 += dy;
+    self.visited[0].insert(self.knots[0]);
+    for i in 1..self.knots.len() {
+      let prev = self.knots[i - 1];
+      let knot = &mut self.knots[i];
+      if knot.0.abs_diff(prev.0) < 2 && knot.1.abs_diff(prev.1) < 2 {
+        // Touching, so the rest of the rope stays put
+        break;
       }
+      knot.0 += (prev.0 - knot.0).signum();
+      knot.1 += (prev.1 - knot.1).signum();
+      self.visited[i].insert(*knot);
     }
-    (x_diff, 0) => {
-      // Same col
-      let x_c = (prev.0 - knot.0) / x_diff as i32;
-      for _ in 0..(x_diff - 1) {
-        knot.0 += x_c;
-        record(knot, visited_pos);
-      }
+  }
+
+  pub fn apply(&mut self, (dir, steps): (char, i32)) {
+    for _ in 0..steps {
+      self.step(dir);
     }
-    (mut x_diff, mut y_diff) => {
-      let x_c = (prev.0 - knot.0) / x_diff as i32;
-      let y_c = (prev.1 - knot.1) / y_diff as i32;
-      while x_diff > 0 && y_diff > 0 && !(x_diff == 1 && y_diff == 1) {
-        knot.0 += x_c;
-        knot.1 += y_c;
-        record(knot, visited_pos);
-        x_diff = knot.0.abs_diff(prev.0);
-        y_diff = knot.1.abs_diff(prev.1);
-      }
-      while knot.0.abs_diff(prev.0) > 1 {
-        knot.0 += x_c;
-        record(knot, visited_pos);
-      }
-      while knot.1.abs_diff(prev.1) > 1 {
-        knot.1 += y_c;
-        record(knot, visited_pos);
-      }
+  }
+
+  pub fn apply_all(&mut self, moves: &[(char, i32)]) {
+    for m in moves {
+      self.apply(*m);
     }
   }
-  true
-}
 
-pub fn part2(moves: &Vec<(char, i32)>) -> usize {
-  let mut visit_pos: HashSet<(i32, i32)> = HashSet::new();
-  let knots_count = 10;
-  let knots: Vec<RefCell<(i32, i32)>> = vec![RefCell::from((0, 0)); knots_count];
-  visit_pos.insert(*knots.last().unwrap().borrow());
-  let mut needs_move;
-
-  for (dir, steps) in moves {
-    for _ in 0..*steps {
-      move_head(&mut knots.first().unwrap().borrow_mut(), dir, &1);
-      needs_move = true;
-      for i in 1..knots_count - 1 {
-        needs_move = move_knot_wo_record(&mut knots.get(i).unwrap().borrow_mut(),
-                                         &knots.get(i - 1).unwrap().borrow(),
-                                         &mut visit_pos);
-        if !needs_move {
-          break;
-        }
-      }
-      if needs_move {
-        move_knot_w_record(&mut knots.last().unwrap().borrow_mut(),
-                           &knots.get(knots_count - 2).unwrap().borrow(),
-                           &mut visit_pos);
+  /// The knot positions after every single step of the moves
+  pub fn trace(&mut self, moves: &[(char, i32)]) -> Vec<Vec<Position>> {
+    let mut states = Vec::new();
+    for (dir, steps) in moves {
+      for _ in 0..*steps {
+        self.step(*dir);
+        states.push(self.knots.clone());
       }
     }
+    states
+  }
+
+  /// `H` for the head, `T` for the tail of a two knot rope, otherwise the knot number in base 36
+  /// and `*` for the knots past `z`, keeping `#` for visited cells
+  fn label(&self, knot: usize) -> char {
+    match knot {
+      0 => 'H',
+      _ if knot == self.knots.len() - 1 && knot == 1 => 'T',
+      _ => char::from_digit(knot as u32, 36).unwrap_or('*'),
+    }
+  }
+
+  /// The corners (bottom left, top right) enclosing the start and everything any knot visited
+  pub fn bounds(&self) -> (Position, Position) {
+    self.visited.iter().flatten().fold(((0, 0), (0, 0)), |(min, max), p| {
+      ((min.0.min(p.0), min.1.min(p.1)), (max.0.max(p.0), max.1.max(p.1)))
+    })
+  }
+
+  /// Draw the knots within the given corners as in the puzzle, earlier knots covering later ones
+  pub fn render_in(&self, min: Position, max: Position) -> String {
+    self.draw(min, max, |pos| {
+      self.knots.iter().position(|k| *k == pos).map(|i| self.label(i))
+    })
+  }
+
+  pub fn render(&self) -> String {
+    let (min, max) = self.bounds();
+    self.render_in(min, max)
+  }
+
+  /// Draw the positions visited by the given knot with `#`
+  pub fn render_visited(&self, knot: usize) -> String {
+    let (min, max) = self.bounds();
+    self.draw(min, max, |pos| {
+      if pos != (0, 0) && self.visited[knot].contains(&pos) { Some('#') } else { None }
+    })
+  }
+
+  fn draw(&self, min: Position, max: Position, cell: impl Fn(Position) -> Option<char>) -> String {
+    (min.1..=max.1).rev().map(|y| {
+      (min.0..=max.0).map(|x| match cell((x, y)) {
+        Some(c) => c,
+        None if (x, y) == (0, 0) => 's',
+        None => '.',
+      }).collect::<String>()
+    }).collect::<Vec<String>>().join("\n")
   }
-  visit_pos.len()
 }
 
 #[cfg(test)]
 mod tests {
-  use std::collections::HashSet;
-  use crate::day9::{generator, move_knot_wo_record, part1, part2};
+  use crate::day9::{generator, part1, part2, Rope};
 
   fn input() -> String {
     vec![
@@ -185,10 +209,52 @@ mod tests {
 
   #[test]
   fn test_moves() {
-    let prev = (5, 4);
-    let mut curr = (0, 0);
-    let mut visits: HashSet<(i32, i32)> = HashSet::new();
-    move_knot_wo_record(&mut curr, &prev, &mut visits);
-    assert_eq!(&(4, 4), &curr);
+    let mut rope = Rope::new(2);
+    rope.apply(('R', 5));
+    rope.apply(('U', 4));
+    assert_eq!(&[(5, 4), (5, 3)], rope.knots());
+    assert_eq!(10, rope.visited(0).len());
+    assert_eq!(8, rope.visited(1).len());
   }
-}
\ No newline at end of file
+
+  #[test]
+  fn test_visited_by_any_knot() {
+    let moves = generator(input_2().as_str());
+    let mut rope = Rope::new(10);
+    rope.apply_all(&moves);
+    assert_eq!(36, rope.tail_visited().len());
+    assert_eq!(rope.visited(9), rope.tail_visited());
+    let mut two_knots = Rope::new(2);
+    two_knots.apply_all(&moves);
+    assert_eq!(two_knots.visited(1), rope.visited(1));
+    assert!(rope.visited(1).len() > rope.visited(5).len());
+  }
+
+  #[test]
+  fn test_trace() {
+    let moves = generator(input().as_str());
+    let states = Rope::new(2).trace(&moves);
+    assert_eq!(24, states.len());
+    assert_eq!(vec![(1, 0), (0, 0)], states[0]);
+    assert_eq!(vec![(4, 1), (3, 0)], states[4]);
+    assert_eq!(vec![(2, 2), (1, 2)], states[23]);
+  }
+
+  #[test]
+  fn test_render() {
+    let moves = generator(input().as_str());
+    let mut rope = Rope::new(2);
+    rope.apply_all(&moves[..1]);
+    assert_eq!(["......", "......", "......", "......", "s..TH."].join("\n"), rope.render_in((0, 0), (5, 4)));
+    rope.apply_all(&moves[1..]);
+    assert_eq!(["......", "......", ".TH...", "......", "s....."].join("\n"), rope.render_in((0, 0), (5, 4)));
+    assert_eq!(["..##..", "...##.", ".####.", "....#.", "s###.."].join("\n"), rope.render_visited(1));
+
+    let mut rope = Rope::new(10);
+    rope.apply_all(&moves[..2]);
+    assert_eq!(["....H.", "....1.", "..432.", ".5....", "6....."].join("\n"), rope.render_in((0, 0), (5, 4)));
+
+    let rope = Rope::new(40);
+    assert_eq!(vec!['H', '1', 'z', '*'], [0, 1, 35, 36].map(|k| rope.label(k)).to_vec());
+  }
+}
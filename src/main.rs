@@ -18,6 +18,91 @@ struct Args {
     /// a single day to execute (defaults to all)
     #[argh(positional)]
     day: Option<usize>,
+
+    #[argh(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(FromArgs)]
+#[argh(subcommand)]
+enum Command {
+    Snafu(SnafuArgs),
+    Mix(MixArgs),
+}
+
+#[derive(FromArgs)]
+/// Convert numbers between SNAFU and decimal
+#[argh(subcommand, name = "snafu")]
+struct SnafuArgs {
+    /// convert from decimal to SNAFU instead
+    #[argh(switch, short = 'd')]
+    from_decimal: bool,
+
+    /// the numbers to convert
+    #[argh(positional)]
+    numbers: Vec<String>,
+}
+
+/// Print each number converted between SNAFU and decimal, or why it could not be.
+fn convert_snafu(args: &SnafuArgs) {
+    for number in &args.numbers {
+        let converted = if args.from_decimal {
+            number.parse::<i128>()
+              .map(|n| day25::Snafu::from(n).to_string())
+              .map_err(|e| e.to_string())
+        } else {
+            number.parse::<day25::Snafu>()
+              .and_then(|n| i128::try_from(&n))
+              .map(|n| n.to_string())
+              .map_err(|e| e.to_string())
+        };
+        match converted {
+            Ok(converted) => println!("{} {}", number, converted.bold()),
+            Err(e) => println!("{} {}", number, e.red()),
+        }
+    }
+}
+
+#[derive(FromArgs)]
+/// Decrypt the day 20 numbers, showing them after each round of mixing
+#[argh(subcommand, name = "mix")]
+struct MixArgs {
+    /// the decryption key to multiply the numbers by
+    #[argh(option, short = 'k', default = "day20::DECRYPTION_KEY")]
+    key: i64,
+
+    /// how many rounds to mix
+    #[argh(option, short = 'r', default = "10")]
+    rounds: usize,
+
+    /// an offset from the 0 to read a grove coordinate at (defaults to 1000, 2000 and 3000)
+    #[argh(option, short = 'o')]
+    offset: Vec<usize>,
+
+    /// the file of numbers (defaults to day20.txt in the input directory)
+    #[argh(positional)]
+    file: Option<String>,
+}
+
+/// Print the numbers before and after each round of mixing and the grove coordinates they give.
+fn mix_numbers(args: &MixArgs, input_dir: &str) {
+    let filename = args.file.clone().unwrap_or_else(|| format!("{input_dir}/day20.txt"));
+    let input = fs::read_to_string(&filename).expect("Can't read the numbers");
+    let numbers = day20::generator(&input);
+    let offsets = if args.offset.is_empty() { day20::GROVE_OFFSETS.to_vec() } else { args.offset.clone() };
+    let join = |numbers: &[i64]| numbers.iter().map(|n| n.to_string()).collect::<Vec<String>>().join(", ");
+
+    println!("{}", "Initial arrangement:".bold());
+    println!("{}\n", join(&numbers.iter().map(|n| n * args.key).collect::<Vec<i64>>()));
+    let decryption = day20::decrypt_with::<day20::Treap, _>(&numbers, args.key, args.rounds, &offsets, |round, sequence| {
+        let rounds = if round == 1 { "round" } else { "rounds" };
+        println!("{}", format!("After {round} {rounds} of mixing:").bold());
+        println!("{}\n", join(sequence));
+    });
+    for (offset, coordinate) in offsets.iter().zip(&decryption.coordinates) {
+        println!("Number {offset} after 0: {coordinate}");
+    }
+    println!("{} {}", "Sum:".bold(), decryption.coordinates.iter().sum::<i64>());
 }
 
 #[derive(Default,Deserialize,Serialize)]
@@ -76,6 +161,17 @@ fn read_inputs(in_dir: &str) -> io::Result<Vec<String>> {
 
 fn main() {
     let args: Args = argh::from_env();
+    match &args.command {
+        Some(Command::Snafu(snafu_args)) => {
+            convert_snafu(snafu_args);
+            return;
+        }
+        Some(Command::Mix(mix_args)) => {
+            mix_numbers(mix_args, &args.input);
+            return;
+        }
+        None => {}
+    }
     // Did the user pick a single day to run
     let day_filter: Option<usize> = match args.day {
         Some(day) => {
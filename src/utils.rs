@@ -1,5 +1,7 @@
 use colored::Colorize;
+use std::cell::RefCell;
 use std::cmp::min;
+use std::collections::BTreeMap;
 use std::fmt;
 use std::time;
 
@@ -34,6 +36,34 @@ fn pretty_print(f: &mut fmt::Formatter<'_>, line: &str,
   }
 }
 
+/// Print the statistics recorded by a part below it.
+fn pretty_print_stats(f: &mut fmt::Formatter<'_>, stats: &[(String, u64)]) -> fmt::Result {
+  for (name, value) in stats {
+    writeln!(f, "{}", format!("      {}: {}", name, value).dimmed())?;
+  }
+  Ok(())
+}
+
+thread_local! {
+  static STATS: RefCell<BTreeMap<String, u64>> = const { RefCell::new(BTreeMap::new()) };
+}
+
+/// Add to a named counter that the runner reports along with the part that recorded it
+pub fn add_stat(name: &str, value: u64) {
+  STATS.with(|stats| {
+    let mut stats = stats.borrow_mut();
+    match stats.get_mut(name) {
+      Some(total) => *total = total.saturating_add(value),
+      None => { stats.insert(name.to_string(), value); }
+    }
+  });
+}
+
+/// Remove and return the counters recorded since the last call
+pub fn take_stats() -> Vec<(String, u64)> {
+  STATS.with(|stats| std::mem::take(&mut *stats.borrow_mut()).into_iter().collect())
+}
+
 /// Time the given function, returning its result and the elapsed time
 pub fn time<T>(func: &dyn Fn() -> T) -> (time::Duration, T) {
   let start = time::Instant::now();
@@ -48,6 +78,8 @@ pub struct DayResult {
   pub generate_time: time::Duration,
   pub part1: (time::Duration, String),
   pub part2: (time::Duration, String),
+  pub part1_stats: Vec<(String, u64)>,
+  pub part2_stats: Vec<(String, u64)>,
 }
 
 impl DayResult {
@@ -68,7 +100,9 @@ impl fmt::Display for DayResult {
     writeln!(f, "{} {}", self.pretty_day().bold(), duration.dimmed())?;
     pretty_print(f," · Generator", self.generate_time, None)?;
     pretty_print(f, " · Part 1", self.part1.0, Some(&self.part1.1))?;
-    pretty_print(f, " · Part 2", self.part2.0, Some(&self.part2.1))
+    pretty_print_stats(f, &self.part1_stats)?;
+    pretty_print(f, " · Part 2", self.part2.0, Some(&self.part2.1))?;
+    pretty_print_stats(f, &self.part2_stats)
   }
 }
 
@@ -82,12 +116,17 @@ macro_rules! day_list_internal {
         pub const FUNCS : &[&dyn Fn(&str) -> crate::utils::DayResult] = &[
             $(&|data| {
                 let (generate_time, input) = crate::utils::time(&|| $day::generator(data));
+                crate::utils::take_stats();
                 let part1 = crate::utils::time(&|| $day::part1(&input));
+                let part1_stats = crate::utils::take_stats();
                 let part2 = crate::utils::time(&|| $day::part2(&input));
+                let part2_stats = crate::utils::take_stats();
                 crate::utils::DayResult{day: stringify!($day).to_string(),
                           generate_time,
                           part1: (part1.0, part1.1.to_string()),
-                          part2: (part2.0, part2.1.to_string())}},)*
+                          part2: (part2.0, part2.1.to_string()),
+                          part1_stats,
+                          part2_stats}},)*
         ];
 
         /// Define the list of implemented day names.